@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use wfc::core::CoreState;
+use wfc::model::{Model, ModelOptions};
+use wfc::{Propagation, SolveOptions};
+
+//
+// Solve large outputs as a single block so that every removal goes
+// through propagate, sequentially and spread over the rayon threads
+//
+fn propagation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("propagation");
+    group.sample_size(10);
+
+    for sample in ["rooms", "Flowers"] {
+        let path = format!("samples/{}.png", sample);
+        let model = Arc::new(Model::create(&path, &ModelOptions::new(3)).unwrap());
+
+        for propagation in [Propagation::Sequential, Propagation::Parallel] {
+            let options = SolveOptions {
+                seed: 1,
+                blocks: (1, 1),
+                propagation,
+                ..SolveOptions::default()
+            };
+            let id = BenchmarkId::new(format!("{:?}", propagation), sample);
+            group.bench_function(id, |b| {
+                b.iter(|| CoreState::par_solve(&model, 128, 128, &options).unwrap())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, propagation);
+criterion_main!(benches);
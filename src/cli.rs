@@ -1,12 +1,181 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-#[derive(Parser, Default, Debug)]
-pub struct Args {
-    // Name of the image file
+use wfc::{Edge, Propagation, Strategy};
+
+//
+// Generates an output when called without a subcommand
+//
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub model: Option<ModelArgs>,
+    #[command(flatten)]
+    pub generate: Option<GenerateArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    // Check that an output follows every adjacency rule of the model
+    Validate(ValidateArgs),
+    // Compile models ahead of time
+    #[command(subcommand)]
+    Model(ModelCommand),
+    // Generate an output from a compiled model
+    Generate(GenerateModelArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModelCommand {
+    // Build the model once and save it for generate --model
+    Build(BuildArgs),
+}
+
+// Where the model comes from and how it is built
+#[derive(clap::Args, Default, Debug)]
+pub struct ModelArgs {
+    // Name of the image file (or of the tileset description with --tileset)
     pub img_path: String,
-    pub n_dimensions: usize,
+    // Pattern size, N for NxN patterns or WIDTHxHEIGHT, e.g. 4x2
+    #[arg(value_parser = parse_pattern_size)]
+    pub n_dimensions: (usize, usize),
+    // Augment patterns with the first 1..8 rotations and reflections
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub symmetry: u8,
+    // Use the simple tiled model, n_dimensions and symmetry are ignored
+    #[arg(long)]
+    pub tileset: bool,
+    // Let patterns wrap around the input edges, pass false for inputs
+    // whose edges mean something (e.g. sky on top, ground on bottom)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub periodic_input: bool,
+    // Treat every fully transparent pixel of the input as the same,
+    // whatever colour it has
+    #[arg(long)]
+    pub merge_transparent: bool,
+    // Reduce the input to this many colours (median cut) so that
+    // nearly identical colours don't each make up new patterns
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "palette")]
+    pub colours: Option<u32>,
+    // Snap the input to the colours of a palette, either an image or
+    // a text file of hex colours such as #ff8000
+    #[arg(long)]
+    pub palette: Option<String>,
+}
+
+// Output settings, the model comes first
+#[derive(clap::Args, Default, Debug)]
+pub struct GenerateArgs {
     pub width: usize,
     pub height: usize,
+    // How to recover from contradictions
+    #[arg(long, value_enum, default_value_t = Strategy::Restart)]
+    pub strategy: Strategy,
+    // Whether big waves of removals are propagated in parallel
+    #[arg(long, value_enum, default_value_t = Propagation::Sequential)]
+    pub propagation: Propagation,
+    // Make the output tile seamlessly in both axes
+    #[arg(long)]
+    pub periodic_output: bool,
+    // Output borders which only take patterns found on the same edge
+    // of the input, e.g. --edges bottom to keep the ground at the bottom
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub edges: Vec<Edge>,
+    // Partially painted output to fill in, fully transparent pixels
+    // are unknown, it has to be the size of the rendered output
+    #[arg(long)]
+    pub guide: Option<String>,
+    // Fill in the pixels which aren't black in this mask instead of
+    // the transparent ones of the guide
+    #[arg(long, requires = "guide")]
+    pub mask: Option<String>,
+    // Grid of blocks solved in parallel, as COLUMNSxROWS
+    #[arg(long, default_value = "2x2", value_parser = parse_blocks)]
+    pub blocks: (usize, usize),
+    // Seed for every random choice, picked at random if missing
+    #[arg(long)]
+    pub seed: Option<u64>,
+    // Where to save the output, the extension picks the format
+    // (png, bmp, tga, ppm, ... anything the image crate writes)
+    #[arg(long, default_value = "image.png")]
+    pub output: String,
+    // Replace the output if the file already exists
+    #[arg(long)]
+    pub force: bool,
+    // When every attempt fails or the constraints contradict each other,
+    // save what was left of each cell here, undecided cells blend their
+    // possible patterns
     #[arg(long)]
-    pub rotation: bool,
+    pub render_partial: Option<String>,
+    // Save the collapse as an animated GIF when this ends in .gif,
+    // otherwise as numbered PNGs in this directory
+    #[arg(long)]
+    pub frames: Option<String>,
+    // Collapses between two frames, 1% of the output cells by default
+    #[arg(long, requires = "frames", value_parser = clap::value_parser!(u32).range(1..))]
+    pub snapshot_every: Option<u32>,
+    // Only print errors, without the progress bar
+    #[arg(long, conflicts_with = "json_log")]
+    pub quiet: bool,
+    // Print every event as a line of JSON instead of the progress bar
+    #[arg(long)]
+    pub json_log: bool,
+}
+
+#[derive(clap::Args, Default, Debug)]
+pub struct BuildArgs {
+    #[command(flatten)]
+    pub model: ModelArgs,
+    // Where to write the compiled model
+    pub output: String,
+}
+
+#[derive(clap::Args, Default, Debug)]
+pub struct GenerateModelArgs {
+    // Compiled model written by model build
+    #[arg(long)]
+    pub model: String,
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+}
+
+#[derive(clap::Args, Default, Debug)]
+pub struct ValidateArgs {
+    // Must match the options the output was generated with
+    #[command(flatten)]
+    pub model: ModelArgs,
+    // Generated image to check
+    pub output: String,
+    // Also check the pairs across the edges of the output
+    #[arg(long)]
+    pub periodic_output: bool,
+}
+
+//
+// Parse a block grid such as "4x2"
+//
+fn parse_blocks(arg: &str) -> Result<(usize, usize), String> {
+    match arg.split_once(['x', 'X']) {
+        Some((columns, rows)) => Ok((parse_positive(columns)?, parse_positive(rows)?)),
+        None => Err("expected COLUMNSxROWS, e.g. 4x2".to_string()),
+    }
+}
+
+//
+// Parse a pattern size, "3" for square patterns or "4x2"
+//
+fn parse_pattern_size(arg: &str) -> Result<(usize, usize), String> {
+    match arg.split_once(['x', 'X']) {
+        Some((width, height)) => Ok((parse_positive(width)?, parse_positive(height)?)),
+        None => parse_positive(arg).map(|n| (n, n)),
+    }
+}
+
+fn parse_positive(arg: &str) -> Result<usize, String> {
+    match arg.trim().parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!("\"{}\" is not a positive number", arg)),
+        Ok(count) => Ok(count),
+    }
 }
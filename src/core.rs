@@ -1,19 +1,24 @@
 use std::collections::{BinaryHeap, VecDeque};
+use std::sync::Arc;
 use std::time::Instant;
 
-use crate::data::colour::Rgb;
+use crate::data::colour::Rgba;
 
-use crate::data::direction::ALL_DIRECTIONS;
+use crate::data::direction::{Direction, ALL_DIRECTIONS};
+use crate::data::grid2d::Grid2D;
 use crate::data::vector2::Vector2;
-use crate::{data::grid2d::Grid2D, model::Model};
+use crate::model::{Model, ModelOptions};
 
 use crate::entropy_coord::EntropyCoord;
+use crate::error::{Error, Result};
+use crate::observer::{Event, Observer, Silent};
+use crate::seed::{derive_seed, make_rng, Seed};
+use rand::rngs::StdRng;
 use rand::Rng;
 use rayon::prelude::{
     IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator,
 };
 
-#[allow(dead_code)]
 pub type TileIndex = usize;
 
 // Indicate that the potential for tile_index appearing
@@ -25,6 +30,156 @@ pub struct RemovalUpdate {
     coord: Vector2,
 }
 
+// A single change made to the grid while a decision is active,
+// kept so that it can be reverted when backtracking.
+#[derive(Debug, Clone)]
+enum TrailEntry {
+    // The cell at `coord` was collapsed, `removed` holds every
+    // other tile which was possible before the collapse.
+    Collapse {
+        coord: Vector2,
+        removed: Vec<TileIndex>,
+    },
+
+    // The tile was removed from the cell through `remove_tile`
+    Removal(RemovalUpdate),
+
+    // An enabler count of the cell at `coord` was decremented
+    EnablerDecrement {
+        coord: Vector2,
+        tile_index: TileIndex,
+        direction: usize,
+    },
+}
+
+// A collapse decision together with every
+// change it caused while it was the latest one
+#[derive(Debug, Clone)]
+pub struct Decision {
+    coord: Vector2,
+    tile_index: Option<TileIndex>,
+    trail: Vec<TrailEntry>,
+}
+
+impl Decision {
+    fn new(coord: Vector2) -> Decision {
+        Decision {
+            coord,
+            tile_index: None,
+            trail: Vec::new(),
+        }
+    }
+}
+
+// How a subsection recovers from a contradiction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Strategy {
+    // Throw the progress away and run again from the initial state
+    #[default]
+    Restart,
+
+    // Undo the latest decisions until the contradiction disappears
+    Backtrack,
+}
+
+// How propagate spreads a wave of removals over the rows of the grid,
+// both produce exactly the same grid for the same seed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Propagation {
+    // One row after the other on the current thread
+    #[default]
+    Sequential,
+
+    // Rows in parallel once a wave is big enough to pay for it
+    Parallel,
+}
+
+// A border of the output which can be limited to the
+// patterns found along the same edge of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+impl Edge {
+    // Direction the border faces from the middle of the output
+    pub fn direction(self) -> Direction {
+        match self {
+            Edge::Top => Direction::Up,
+            Edge::Right => Direction::Right,
+            Edge::Bottom => Direction::Down,
+            Edge::Left => Direction::Left,
+        }
+    }
+}
+
+// Settings for solving a whole output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolveOptions {
+    pub strategy: Strategy,
+    pub seed: Seed,
+
+    // Wrap the output around its edges so that it tiles seamlessly
+    pub periodic: bool,
+
+    // Borders limited to the input's edge patterns, indexed by
+    // `Edge::direction().to_idx()`, bottom alone is the classic "ground"
+    pub edges: [bool; 4],
+
+    // Number of blocks along x and y which are solved in parallel
+    pub blocks: (usize, usize),
+
+    pub propagation: Propagation,
+
+    // Render a frame of the whole output after every this many
+    // collapses, the frames end up in `Solution::frames`
+    pub snapshot_every: Option<usize>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> SolveOptions {
+        SolveOptions {
+            strategy: Strategy::default(),
+            seed: 0,
+            periodic: false,
+            edges: [false; 4],
+            blocks: (2, 2),
+            propagation: Propagation::default(),
+            snapshot_every: None,
+        }
+    }
+}
+
+// A solved output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    // Tile index of every output cell
+    pub tiles: Grid2D<TileIndex>,
+
+    // Number of times the output was split before every block succeeded
+    pub attempts: usize,
+
+    // Rendered snapshots of the collapse, ending on the finished
+    // output, empty unless `SolveOptions::snapshot_every` is set
+    pub frames: Vec<Grid2D<Rgba>>,
+}
+
+// Number of undone decisions after which a backtracking run gives up
+const BACKTRACK_LIMIT: usize = 10_000;
+
+// Number of times par_solve splits the output before giving up
+const SPLIT_ATTEMPTS: usize = 50;
+
+// Collapsed events a state sends while collapsing all of its cells
+const COLLAPSED_EVENTS: usize = 100;
+
+// Smallest wave of removals which propagate spreads over threads,
+// see the benchmarks in the README
+const PARALLEL_WAVE: usize = 256;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TileEnablerCount {
     // `by_direction[d]` will return the count
@@ -34,7 +189,7 @@ pub struct TileEnablerCount {
 impl TileEnablerCount {
     #[allow(dead_code)]
     pub fn contains_any_zero_count(&self) -> bool {
-        self.by_direction.iter().any(|&count| count == 0)
+        self.by_direction.contains(&0)
     }
 }
 
@@ -90,6 +245,21 @@ impl CoreCell {
         cell
     }
 
+    //
+    // Copy the state of `other` over this cell,
+    // reusing the memory the cell already holds
+    //
+    fn reset_from(&mut self, other: &CoreCell) {
+        self.possible.clone_from(&other.possible);
+        self.sum_of_possible_tile_weights = other.sum_of_possible_tile_weights;
+        self.sum_of_possible_tile_weight_log_weights =
+            other.sum_of_possible_tile_weight_log_weights;
+        self.entropy_noise = other.entropy_noise;
+        self.is_collpased = other.is_collpased;
+        self.tile_enabler_counts
+            .clone_from(&other.tile_enabler_counts);
+    }
+
     //
     // Get the sum of all current possible tile's frequency
     //
@@ -141,14 +311,25 @@ impl CoreCell {
         self.sum_of_possible_tile_weight_log_weights -= freq.1;
     }
 
+    //
+    //  Inverse of remove_tile, put the tile back into
+    //  the cell and restore the cached entropy.
+    //
+    pub fn restore_tile(&mut self, tile_index: TileIndex, model: &Model) {
+        self.possible.insert(tile_index);
+
+        let freq = model.get_relative_freq(tile_index);
+
+        self.sum_of_possible_tile_weights += freq.0;
+        self.sum_of_possible_tile_weight_log_weights += freq.1;
+    }
+
     //
     // Roulette wheel selection algorithm,
     // Choose a random sample with frequency hints taken into account
     //
     #[allow(dead_code)]
-    fn choose_sample_index(&self, context: &Model) -> Option<TileIndex> {
-        let mut rng = rand::thread_rng();
-
+    fn choose_sample_index(&self, context: &Model, rng: &mut impl Rng) -> Option<TileIndex> {
         if self.sum_of_possible_tile_weights == 0 {
             return None;
         }
@@ -200,13 +381,112 @@ pub struct CoreState {
     // collapsed yet, intialized to grid.len()
     pub remaining_uncollapsed_cells: usize,
 
-    // Our wfc model, contains the rules
-    // we need in order to collapse tiles.
-    pub model: Model,
+    // Our wfc model, contains the rules we need in order to
+    // collapse tiles, shared by the blocks and every retry
+    pub model: Arc<Model>,
 
     pub entropy_heap: BinaryHeap<EntropyCoord>,
 
     pub tile_removals: VecDeque<RemovalUpdate>,
+
+    // Stack of collapse decisions, only
+    // filled when running with backtracking
+    pub decisions: Vec<Decision>,
+
+    // Every random choice is drawn from here,
+    // subsections get their own derived seed
+    pub seed: Seed,
+    rng: StdRng,
+
+    // Neighbours wrap around the grid edges
+    pub periodic: bool,
+
+    pub propagation: Propagation,
+
+    // Snapshots of the collapse, only taken when recording
+    pub recording: Option<Recording>,
+
+    // Told about every collapse and how the solving goes
+    pub observer: Arc<dyn Observer>,
+
+    // Which of the blocks this is, None for the full output
+    pub block: Option<usize>,
+}
+
+// Frames rendered while a state is solved
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    // A frame is rendered after every this many collapses
+    pub every: usize,
+    collapses: usize,
+    pub frames: Vec<Grid2D<Rgba>>,
+}
+
+impl Recording {
+    pub fn new(every: usize) -> Recording {
+        Recording {
+            every: every.max(1),
+            ..Recording::default()
+        }
+    }
+}
+
+// Part of the output which is solved on its own
+// once the strips around it have been collapsed
+#[derive(Debug, Clone)]
+pub struct Block {
+    // Position of the top left cell of the state in the full output,
+    // the state starts with a halo of collapsed strip cells which
+    // may wrap around the edges of a periodic output
+    pub origin: Vector2,
+    pub state: CoreState,
+}
+
+//
+// Mark the cells along one axis which belong to a separator strip,
+// a strip as wide as a pattern is centred on every boundary between
+// two blocks and on the seam when the output wraps around
+//
+fn strip_mask(length: usize, blocks: usize, pattern_size: usize, periodic: bool) -> Vec<bool> {
+    let mut mask = vec![false; length];
+    let blocks = blocks.max(1);
+    let first = if periodic { 0 } else { 1 };
+
+    for i in first..blocks {
+        let boundary = (i * length / blocks) as i64;
+        let start = boundary - (pattern_size / 2) as i64;
+
+        for pos in start..start + pattern_size as i64 {
+            if periodic {
+                mask[pos.rem_euclid(length as i64) as usize] = true;
+            } else if (0..length as i64).contains(&pos) {
+                mask[pos as usize] = true;
+            }
+        }
+    }
+
+    mask
+}
+
+//
+// Start and length of every run of cells outside of the strips
+//
+fn runs(mask: &[bool]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+
+    for (pos, &in_strip) in mask.iter().chain([true].iter()).enumerate() {
+        match (in_strip, start) {
+            (false, None) => start = Some(pos),
+            (true, Some(first)) => {
+                runs.push((first, pos - first));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    runs
 }
 
 impl CoreState {
@@ -219,48 +499,38 @@ impl CoreState {
         let collapse_status = self.collapse_cell_at(next_coord);
 
         match collapse_status {
-            RunStatus::Failed => return RunStatus::Failed,
+            RunStatus::Failed => RunStatus::Failed,
             RunStatus::Succeeded => {
-                self.propagate();
                 self.remaining_uncollapsed_cells -= 1;
+
+                // Propagate the effects
+                let status = self.propagate();
+                if status == RunStatus::Succeeded {
+                    self.after_collapse();
+                }
+                status
             }
         }
-
-        // Propagate the effects
-        RunStatus::Succeeded
     }
 
-    pub fn collapse_middle(&mut self) -> (CoreState, CoreState, CoreState, CoreState) {
-        let sample_size = self.model.samples[0].region.width;
-        let middle = self.grid.width / 2;
-        let vertical_middle = self.grid.height / 2;
-
-        let x = middle - ((sample_size / 2) + 1);
-        let y = vertical_middle - ((sample_size / 2) + 1);
+    //
+    // Collapse the separator strips between the blocks, then hand out
+    // every block as its own state, ready to be solved in parallel.
+    //
+    // Returns None if the strips ran into a contradiction.
+    //
+    pub fn split(&mut self, blocks: (usize, usize)) -> Option<Vec<Block>> {
+        let (width, height, periodic) = (self.grid.width, self.grid.height, self.periodic);
 
-        let mut left_entropy = BinaryHeap::<EntropyCoord>::new();
-        let mut left_bottom_entropy = BinaryHeap::<EntropyCoord>::new();
-        let mut right_entropy = BinaryHeap::<EntropyCoord>::new();
-        let mut right_bottom_entropy = BinaryHeap::<EntropyCoord>::new();
+        // Strips are as wide as a pattern across them
+        let column_strips = strip_mask(width, blocks.0, self.model.pattern_width, periodic);
+        let row_strips = strip_mask(height, blocks.1, self.model.pattern_height, periodic);
+        let in_strip = |pos: Vector2| column_strips[pos.x as usize] || row_strips[pos.y as usize];
 
         let mut collapse_target = BinaryHeap::new();
-
-        for pos_y in 0..self.grid.height {
-            for pos_x in 0..self.grid.width {
-                let in_horizontal = pos_x > x && pos_x < x + sample_size + 1;
-                let in_vertical = pos_y > y && pos_y < y + sample_size + 1;
-
-                // Vertical strip
-                if in_horizontal || in_vertical {
-                    let pos = Vector2 {
-                        x: pos_x as i32,
-                        y: pos_y as i32,
-                    };
-                    collapse_target.push(EntropyCoord {
-                        coord: pos,
-                        entropy: self.grid.get(pos).unwrap().entropy(),
-                    });
-                }
+        for (pos, cell) in self.grid.enumerate() {
+            if in_strip(pos) {
+                collapse_target.push(EntropyCoord::new(cell.entropy(), pos));
             }
         }
 
@@ -271,18 +541,18 @@ impl CoreState {
                 continue;
             }
 
-            self.forced_collapse(entropy_coord.coord);
+            if self.forced_collapse(entropy_coord.coord) == RunStatus::Failed {
+                return None;
+            }
 
             for &direction in &ALL_DIRECTIONS {
                 // Propagate the effect to the neighbor in each direction
-                let neighbour_coord = entropy_coord.coord.neighbor(direction);
-
-                let in_horizontal = neighbour_coord.x as usize > x
-                    && (neighbour_coord.x as usize) < x + sample_size + 1;
-                let in_vertical = neighbour_coord.y as usize > y
-                    && (neighbour_coord.y as usize) < y + sample_size + 1;
+                let neighbour_coord = match self.neighbour(entropy_coord.coord, direction) {
+                    Some(coord) => coord,
+                    None => continue,
+                };
 
-                if self.grid.valid_pos(neighbour_coord) && (in_horizontal || in_vertical) {
+                if in_strip(neighbour_coord) {
                     let neighbor_cell = self.grid.get(neighbour_coord).unwrap();
                     if neighbor_cell.entropy() < entropy_coord.entropy {
                         collapse_target.push(EntropyCoord {
@@ -294,132 +564,70 @@ impl CoreState {
             }
         }
 
-        for pos_y in 0..self.grid.height {
-            for pos_x in 0..self.grid.width {
-                let in_horizontal = pos_x > x && pos_x < x + sample_size + 1;
-                let in_vertical = pos_y > y && pos_y < y + sample_size + 1;
-
-                // Vertical strip
-                if in_horizontal || in_vertical {
-                    continue;
-                }
-
-                // top left
-                if pos_x < middle && pos_y < vertical_middle {
-                    left_entropy.push(EntropyCoord {
-                        entropy: self
-                            .grid
-                            .get(Vector2 {
-                                x: pos_x as i32,
-                                y: pos_y as i32,
-                            })
-                            .unwrap()
-                            .entropy(),
-                        coord: Vector2 {
-                            x: pos_x as i32,
-                            y: pos_y as i32,
-                        },
-                    });
-                // bottom left
-                } else if pos_x < middle && pos_y > vertical_middle {
-                    left_bottom_entropy.push(EntropyCoord {
-                        entropy: self
-                            .grid
-                            .get(Vector2 {
-                                x: pos_x as i32,
-                                y: pos_y as i32,
-                            })
-                            .unwrap()
-                            .entropy(),
-                        coord: Vector2 {
-                            x: pos_x as i32,
-                            y: (pos_y - vertical_middle) as i32,
-                        },
-                    });
-                // top right
-                } else if pos_x >= middle && pos_y < vertical_middle {
-                    right_entropy.push(EntropyCoord {
-                        entropy: self
-                            .grid
-                            .get(Vector2 {
-                                x: pos_x as i32,
-                                y: pos_y as i32,
-                            })
-                            .unwrap()
-                            .entropy(),
-                        coord: Vector2 {
-                            x: (pos_x - middle) as i32,
-                            y: pos_y as i32,
-                        },
-                    });
-                // bottom right
-                } else if pos_x >= middle && pos_y > vertical_middle {
-                    right_bottom_entropy.push(EntropyCoord {
-                        entropy: self
-                            .grid
-                            .get(Vector2 {
-                                x: pos_x as i32,
-                                y: pos_y as i32,
-                            })
-                            .unwrap()
-                            .entropy(),
-                        coord: Vector2 {
-                            x: (pos_x - middle) as i32,
-                            y: (pos_y - vertical_middle) as i32,
-                        },
-                    });
-                }
-            }
-        }
-
-        let make_grid = |o_x, o_y, s_x, s_y| {
-            self.grid
-                .clone_range(Vector2 { x: o_x, y: o_y }, Vector2 { x: s_x, y: s_y })
-        };
-
-        let left_grid = make_grid(0, 0, middle as i32, vertical_middle as i32);
-        let left_bottom_grid = make_grid(
-            0,
-            vertical_middle as i32,
-            middle as i32,
-            vertical_middle as i32,
-        );
-        let right_grid = make_grid(middle as i32, 0, middle as i32, vertical_middle as i32);
-        let right_bottom_grid = make_grid(
-            middle as i32,
-            vertical_middle as i32,
-            middle as i32,
-            vertical_middle as i32,
-        );
+        // Every block is the product of a run of columns and a run of rows,
+        // grown by a halo of the collapsed strip cells around it so that
+        // its propagation runs against the same fixed neighbours as the
+        // full output. Halo cells outside of a bounded output are dropped.
+        let halo_before = |start: usize| usize::from(periodic || start > 0);
+        let halo_after = |end: usize, length: usize| usize::from(periodic || end < length);
+
+        let mut split = Vec::new();
+        for (y, block_height) in runs(&row_strips) {
+            for (x, block_width) in runs(&column_strips) {
+                let (left, top) = (halo_before(x), halo_before(y));
+                let halo_width = left + block_width + halo_after(x + block_width, width);
+                let halo_height = top + block_height + halo_after(y + block_height, height);
+
+                let origin = Vector2 {
+                    x: (x - left) as i32,
+                    y: (y - top) as i32,
+                };
+                let grid = Grid2D {
+                    width: halo_width,
+                    height: halo_height,
+                    data: (0..halo_width * halo_height)
+                        .map(|idx| {
+                            let offset = Vector2 {
+                                x: (idx % halo_width) as i32,
+                                y: (idx / halo_width) as i32,
+                            };
+                            let pos = self.grid.wrap(origin + offset);
+                            self.grid.get(pos).unwrap().clone()
+                        })
+                        .collect(),
+                };
 
-        let get_remaining =
-            |grid: &Grid2D<CoreCell>| grid.data.iter().filter(|cell| !cell.is_collpased).count();
+                let remaining_uncollapsed_cells =
+                    grid.data.iter().filter(|cell| !cell.is_collpased).count();
+                let entropy_heap = grid
+                    .enumerate()
+                    .filter(|(_, cell)| !cell.is_collpased)
+                    .map(|(pos, cell)| EntropyCoord::new(cell.entropy(), pos))
+                    .collect();
 
-        let left_remains = get_remaining(&left_grid);
-        let left_bottom_remains = get_remaining(&left_bottom_grid);
-        let right_remains = get_remaining(&right_grid);
-        let right_bottom_remains = get_remaining(&right_bottom_grid);
+                let seed = derive_seed(self.seed, split.len() as u64);
+                let state = CoreState {
+                    grid,
+                    remaining_uncollapsed_cells,
+                    model: Arc::clone(&self.model),
+                    entropy_heap,
+                    tile_removals: VecDeque::new(),
+                    decisions: Vec::new(),
+                    seed,
+                    rng: make_rng(seed),
+                    // The halo already holds the cells across the seam
+                    periodic: false,
+                    propagation: self.propagation,
+                    recording: self.recording.as_ref().map(|r| Recording::new(r.every)),
+                    observer: Arc::clone(&self.observer),
+                    block: Some(split.len()),
+                };
 
-        let make_cs = |grid, remain, entropy| -> CoreState {
-            CoreState {
-                grid,
-                remaining_uncollapsed_cells: remain,
-                model: self.model.clone(),
-                entropy_heap: entropy,
-                tile_removals: VecDeque::new(),
+                split.push(Block { origin, state });
             }
-        };
-
-        let left_cs = make_cs(left_grid, left_remains, left_entropy);
-        let left_bottom_cs = make_cs(left_bottom_grid, left_bottom_remains, left_bottom_entropy);
-        let right_cs = make_cs(right_grid, right_remains, right_entropy);
-        let right_bottom_cs = make_cs(
-            right_bottom_grid,
-            right_bottom_remains,
-            right_bottom_entropy,
-        );
+        }
 
-        (left_cs, right_cs, left_bottom_cs, right_bottom_cs)
+        Some(split)
     }
 
     #[allow(dead_code)]
@@ -429,133 +637,376 @@ impl CoreState {
 
     pub fn par_process(
         path: &str,
-        dimensions: usize,
+        model_options: &ModelOptions,
         width: usize,
         height: usize,
-        rotation: bool,
-    ) -> Vec<Rgb> {
-        println!("Image Processing...");
-
-        let model_creation_time = Instant::now();
-        let mut corestate = CoreState::new(path, dimensions, width, height, rotation);
-        println!(
-            "Model Creation Elapsed Time: {:.2?}",
-            model_creation_time.elapsed()
-        );
+        options: &SolveOptions,
+    ) -> Result<Vec<Rgba>> {
+        let model = Arc::new(Model::create(path, model_options)?);
+        let solution = CoreState::par_solve(&model, width, height, options)?;
+        Ok(model.render(&solution.tiles).data)
+    }
+
+    //
+    // Split the output into blocks, solve them in parallel and
+    // stitch them back together, retrying until every block succeeds.
+    //
+    pub fn par_solve(
+        model: &Arc<Model>,
+        width: usize,
+        height: usize,
+        options: &SolveOptions,
+    ) -> Result<Solution> {
+        CoreState::par_solve_with(model, width, height, options, &[], Arc::new(Silent))
+    }
+
+    //
+    // Same as par_solve, but the given cells are limited to their
+    // allowed tiles before anything is collapsed, and the observer
+    // is told how the solving goes
+    //
+    pub fn par_solve_with(
+        model: &Arc<Model>,
+        width: usize,
+        height: usize,
+        options: &SolveOptions,
+        constraints: &[(Vector2, bit_set::BitSet)],
+        observer: Arc<dyn Observer>,
+    ) -> Result<Solution> {
+        let SolveOptions {
+            strategy,
+            seed,
+            periodic,
+            edges,
+            blocks,
+            propagation,
+            snapshot_every,
+        } = *options;
+
+        // Any other size works, strips which don't leave room
+        // for a block simply take up the whole axis
+        if width == 0 || height == 0 {
+            return Err(Error::OutputTooSmall {
+                width,
+                height,
+                min_size: 1,
+            });
+        }
 
-        let grid_res: Vec<_> = {
-            let mut res = vec![];
+        observer.event(&Event::Started {
+            width,
+            height,
+            seed,
+        });
+        let solve_time = Instant::now();
+
+        let mut attempt = 0;
+        // Blocks of the latest attempt, kept to render where it went wrong
+        let mut failed_blocks = Vec::new();
+        let mut corestate = CoreState::from_model(Arc::clone(model), width, height, seed);
+        corestate.periodic = periodic;
+        corestate.propagation = propagation;
+        corestate.observer = Arc::clone(&observer);
+        if let Some(every) = snapshot_every {
+            corestate.record(every);
+        }
 
-            while res.len() < 4 {
-                let model_split = Instant::now();
+        let (blocks, results) = loop {
+            if attempt == SPLIT_ATTEMPTS {
+                observer.event(&Event::Failed { attempts: attempt });
+                let partial = model.render_superposition(&corestate.overlay(&failed_blocks));
+                return Err(Error::RetriesExhausted {
+                    attempts: attempt,
+                    partial: Box::new(partial),
+                });
+            }
 
-                corestate = CoreState::new(path, dimensions, width, height, rotation);
+            let model_split = Instant::now();
 
-                println!("Attempting Model Split...");
-                let (left, right, left_bottom, right_bottom) = corestate.collapse_middle();
-                println!("Model Split Success... {:.2?}", model_split.elapsed());
+            let attempt_seed = derive_seed(seed, attempt as u64);
+            attempt += 1;
+            if attempt > 1 {
+                observer.event(&Event::Retry { attempt });
+            }
+            corestate.reset(attempt_seed);
 
-                println!();
+            // The same constraints fail on every attempt, so don't retry
+            let reason = if corestate.constrain_edges(&edges) == RunStatus::Failed {
+                Some("no pattern fits the constrained edges")
+            } else if corestate.constrain_cells(constraints) == RunStatus::Failed {
+                Some("no pattern fits the known cells")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                observer.event(&Event::Failed { attempts: attempt });
+                let partial = model.render_superposition(&corestate.overlay(&[]));
+                return Err(Error::Unsatisfiable {
+                    reason: reason.to_string(),
+                    partial: Box::new(partial),
+                });
+            }
 
-                res = vec![left, right, left_bottom, right_bottom]
-                    .par_iter_mut()
-                    .enumerate()
-                    .flat_map(|(id, cs)| cs.restart(id as u8))
-                    .collect();
+            let mut blocks = match corestate.split(blocks) {
+                Some(blocks) => blocks,
+                None => {
+                    observer.event(&Event::Contradiction { block: None });
+                    failed_blocks.clear();
+                    continue;
+                }
+            };
+            observer.event(&Event::Split {
+                attempt,
+                blocks: blocks.len(),
+                elapsed: model_split.elapsed(),
+            });
+
+            let results: Vec<_> = blocks
+                .par_iter_mut()
+                .map(|block| block.state.solve(strategy))
+                .collect();
+
+            if results.iter().all(Option::is_some) {
+                let results: Vec<_> = results.into_iter().flatten().collect();
+                break (blocks, results);
             }
-            res
+            failed_blocks = blocks;
         };
 
-        let left = &grid_res[0];
-        let right = &grid_res[1];
-        let left_bottom = &grid_res[2];
-        let right_bottom = &grid_res[3];
+        // Copy result into output grid, the strips were collapsed
+        // in the full state and the blocks fill in the rest
 
-        // let left_bottom = left_bottom.restart();
+        let mut output_grid = Grid2D::init(width, height, None);
 
-        // println!("Computation Elapsed Time: {:.2?}", compute_time.elapsed());
+        for (coord, cell) in corestate.grid.enumerate() {
+            if cell.is_collpased {
+                output_grid.set(coord, cell.get_the_only_possible_tile_index())?;
+            }
+        }
 
-        // Copy result into output grid
+        // Halo cells are copied back unchanged, they never get collapsed again
+        for (block, grid) in blocks.iter().zip(results.iter()) {
+            for (coord, cell) in grid.enumerate() {
+                if cell.is_collpased {
+                    let pos = output_grid.wrap(block.origin + coord);
+                    output_grid.set(pos, cell.get_the_only_possible_tile_index())?;
+                }
+            }
+        }
+
+        // Every cell has to come from either a strip or a block
+        let tiles = output_grid
+            .enumerate()
+            .map(|(coord, tile_index)| tile_index.ok_or(Error::Uncollapsed(coord)))
+            .collect::<Result<Vec<_>>>()?;
+        let tiles = Grid2D {
+            width,
+            height,
+            data: tiles,
+        };
+        let frames = corestate.stitch_frames(&blocks, model.render(&tiles));
+        observer.event(&Event::Solved {
+            attempts: attempt,
+            elapsed: solve_time.elapsed(),
+        });
 
-        let mut output_grid = Grid2D::init(width, height, 0);
+        Ok(Solution {
+            tiles,
+            attempts: attempt,
+            frames,
+        })
+    }
 
-        for (coord, cell) in left.enumerate() {
-            if let Some(tile_index) = cell.get_the_only_possible_tile_index() {
-                output_grid.set(coord, tile_index);
+    //
+    // Possible tiles of every cell of the full output, with
+    // the states of the blocks laid over the strips
+    //
+    fn overlay(&self, blocks: &[Block]) -> Grid2D<bit_set::BitSet> {
+        let mut cells = self.superposition();
+        for block in blocks {
+            for (coord, cell) in block.state.grid.enumerate() {
+                let pos = cells.wrap(block.origin + coord);
+                if let Some(possible) = cells.get_mut(pos) {
+                    possible.clone_from(&cell.possible);
+                }
             }
         }
+        cells
+    }
 
-        for (coord, cell) in right.enumerate() {
-            if let Some(tile_index) = cell.get_the_only_possible_tile_index() {
-                output_grid.set(
-                    Vector2 {
-                        x: coord.x + (width / 2) as i32,
-                        y: coord.y,
-                    },
-                    tile_index,
-                );
-            }
+    //
+    // Tiles each cell may still become
+    //
+    pub fn superposition(&self) -> Grid2D<bit_set::BitSet> {
+        Grid2D {
+            width: self.grid.width,
+            height: self.grid.height,
+            data: self
+                .grid
+                .data
+                .iter()
+                .map(|cell| cell.possible.clone())
+                .collect(),
         }
+    }
 
-        for (coord, cell) in left_bottom.enumerate() {
-            if let Some(tile_index) = cell.get_the_only_possible_tile_index() {
-                output_grid.set(
-                    Vector2 {
-                        x: coord.x,
-                        y: coord.y + (height / 2) as i32,
-                    },
-                    tile_index,
-                );
+    //
+    // Render the state as it is, see `Model::render_superposition`
+    //
+    pub fn render(&self) -> Grid2D<Rgba> {
+        self.model.render_superposition(&self.superposition())
+    }
+
+    //
+    // Start rendering a frame after every `every` collapses
+    //
+    pub fn record(&mut self, every: usize) {
+        self.recording = Some(Recording::new(every));
+    }
+
+    //
+    // Tell the observer about every hundredth of the cells
+    // collapsed, and render a frame if one is due
+    //
+    fn after_collapse(&mut self) {
+        let step = (self.grid.data.len() / COLLAPSED_EVENTS).max(1);
+        if self.remaining_uncollapsed_cells.is_multiple_of(step) {
+            self.observer.event(&Event::Collapsed {
+                block: self.block,
+                remaining: self.remaining_uncollapsed_cells,
+            });
+        }
+
+        let due = match &mut self.recording {
+            Some(recording) => {
+                recording.collapses += 1;
+                recording.collapses % recording.every == 0
+            }
+            None => false,
+        };
+        if due {
+            let frame = self.render();
+            if let Some(recording) = &mut self.recording {
+                recording.frames.push(frame);
             }
         }
+    }
 
-        for (coord, cell) in right_bottom.enumerate() {
-            if let Some(tile_index) = cell.get_the_only_possible_tile_index() {
-                output_grid.set(
-                    Vector2 {
-                        x: coord.x + (width / 2) as i32,
-                        y: coord.y + (height / 2) as i32,
-                    },
-                    tile_index,
-                );
+    //
+    // Frames of the whole output: the strips as they were collapsed,
+    // then the blocks laid over them one step at a time, where a block
+    // which is already done keeps its last frame, and finally `output`
+    //
+    fn stitch_frames(&self, blocks: &[Block], output: Grid2D<Rgba>) -> Vec<Grid2D<Rgba>> {
+        let mut frames = match &self.recording {
+            Some(recording) => recording.frames.clone(),
+            None => return Vec::new(),
+        };
+
+        let recorded = |block: &Block| match &block.state.recording {
+            Some(recording) => recording.frames.clone(),
+            None => Vec::new(),
+        };
+        let recorded: Vec<Vec<Grid2D<Rgba>>> = blocks.iter().map(recorded).collect();
+        let steps = recorded.iter().map(Vec::len).max().unwrap_or(0);
+
+        let cell_size = self.model.cell_size() as i32;
+        let base = self.render();
+        for step in 0..steps {
+            let mut frame = base.clone();
+            for (block, block_frames) in blocks.iter().zip(&recorded) {
+                let pixels = match block_frames.get(step).or(block_frames.last()) {
+                    Some(pixels) => pixels,
+                    None => continue,
+                };
+                for (offset, &colour) in pixels.enumerate() {
+                    let cell = self.grid.wrap(
+                        block.origin
+                            + Vector2 {
+                                x: offset.x / cell_size,
+                                y: offset.y / cell_size,
+                            },
+                    );
+                    let pixel = Vector2 {
+                        x: cell.x * cell_size + offset.x % cell_size,
+                        y: cell.y * cell_size + offset.y % cell_size,
+                    };
+                    if let Some(target) = frame.get_mut(pixel) {
+                        *target = colour;
+                    }
+                }
             }
+            frames.push(frame);
         }
 
-        output_grid
-            .data
-            .iter()
-            .map(|&sample_id| corestate.model.samples[sample_id].get_top_left_pixel())
-            .collect()
+        frames.push(output);
+        frames
+    }
+
+    //
+    // Solve the subsection with the given strategy, a failed state
+    // is left where its last attempt ran into a contradiction
+    //
+    pub fn solve(&mut self, strategy: Strategy) -> Option<Grid2D<CoreCell>> {
+        let solve_time = Instant::now();
+        let grid = match strategy {
+            Strategy::Restart => self.restart(),
+            Strategy::Backtrack => self.backtrack(),
+        }?;
+
+        self.observer.event(&Event::BlockFinished {
+            block: self.block,
+            elapsed: solve_time.elapsed(),
+        });
+        Some(grid)
+    }
+
+    pub fn backtrack(&mut self) -> Option<Grid2D<CoreCell>> {
+        let (status, grid) = self.run_backtracking();
+
+        if status == RunStatus::Succeeded {
+            Some(grid.clone())
+        } else {
+            self.observer
+                .event(&Event::Contradiction { block: self.block });
+            None
+        }
     }
 
-    pub fn restart(&mut self, process_id: u8) -> Option<Grid2D<CoreCell>> {
+    pub fn restart(&mut self) -> Option<Grid2D<CoreCell>> {
         let snapshot = self.clone();
 
         let retry_count = 30;
-        let mut count = 0;
+        let mut count: u64 = 0;
         loop {
             count += 1;
-            let mut candidates = vec![snapshot.clone(); 4];
-
-            let candidates_result = candidates
-                .par_iter_mut()
-                .flat_map(|candidate| {
-                    let (status, grid) = candidate.run();
-
-                    if status == RunStatus::Succeeded {
-                        Ok(grid)
-                    } else {
-                        Err(())
-                    }
+            let mut candidates = (0..4)
+                .map(|idx| {
+                    let mut candidate = snapshot.clone();
+                    candidate.reseed(derive_seed(snapshot.seed, count * 4 + idx));
+                    candidate
                 })
-                .find_any(|_| true);
+                .collect::<Vec<_>>();
+
+            // Take the first successful candidate by position, not
+            // the first one to finish, so that the result is reproducible
+            let succeeded = candidates.par_iter_mut().position_first(|candidate| {
+                let solved = candidate.run().0 == RunStatus::Succeeded;
+                if !solved {
+                    candidate.observer.event(&Event::Contradiction {
+                        block: candidate.block,
+                    });
+                }
+                solved
+            });
 
-            if let Some(candid_res) = candidates_result {
-                println!("Subsection Completed: {}", process_id);
-                return Some(candid_res.clone());
+            if let Some(idx) = succeeded {
+                *self = candidates.swap_remove(idx);
+                return Some(self.grid.clone());
             }
 
             if count > retry_count {
+                *self = candidates.swap_remove(0);
                 return None;
             }
         }
@@ -563,44 +1014,81 @@ impl CoreState {
 
     pub fn new(
         path: &str,
-        dimensions: usize,
+        model_options: &ModelOptions,
         width: usize,
         height: usize,
-        rotation: bool,
+        seed: Seed,
+    ) -> Result<CoreState> {
+        let model = Model::create(path, model_options)?;
+        Ok(CoreState::from_model(model, width, height, seed))
+    }
+
+    //
+    // Create a fresh state for an output of the given size
+    // from an already built model, which may be shared.
+    //
+    pub fn from_model(
+        model: impl Into<Arc<Model>>,
+        width: usize,
+        height: usize,
+        seed: Seed,
     ) -> CoreState {
-        let model = Model::create(path, dimensions, rotation);
+        let model = model.into();
         let grid = Grid2D::init(width, height, CoreCell::new(model.size(), &model));
-        let remaining_uncollapsed_cells = grid.size();
 
         let mut cs = CoreState {
             grid,
-            remaining_uncollapsed_cells,
+            remaining_uncollapsed_cells: 0,
             model,
             entropy_heap: BinaryHeap::new(),
             tile_removals: VecDeque::new(),
+            decisions: Vec::new(),
+            seed,
+            rng: make_rng(seed),
+            periodic: false,
+            propagation: Propagation::default(),
+            recording: None,
+            observer: Arc::new(Silent),
+            block: None,
         };
 
-        cs.distribute_entropy_noise();
+        cs.reset(seed);
+        cs
+    }
 
-        let enabler_counts = cs.model.get_initial_tile_enabler_counts();
+    //
+    // Put every cell back in superposition and start over from the
+    // given seed, the model and the grid allocation are kept
+    //
+    pub fn reset(&mut self, seed: Seed) {
+        self.reseed(seed);
+        self.remaining_uncollapsed_cells = self.grid.size();
+        self.tile_removals.clear();
+        self.decisions.clear();
+        if let Some(recording) = &mut self.recording {
+            *recording = Recording::new(recording.every);
+        }
 
-        // Set the enabler count for all cells
-        cs.grid.data.par_iter_mut().for_each(|cell: &mut CoreCell| {
-            cell.tile_enabler_counts = enabler_counts.clone();
-        });
+        let mut initial = CoreCell::new(self.model.size(), &self.model);
+        initial.tile_enabler_counts = self.model.get_initial_tile_enabler_counts();
+
+        self.grid
+            .data
+            .par_iter_mut()
+            .for_each(|cell: &mut CoreCell| {
+                cell.reset_from(&initial);
+            });
+
+        self.distribute_entropy_noise();
 
         // Fill the binary heap with the new
         // entropy information after adding noise
-        cs.entropy_heap = (0..cs.grid.size())
-            .into_par_iter()
-            .map(|idx| {
-                let coord = cs.grid.to_coord(idx).unwrap();
-                let entropy = cs.grid.get(coord).unwrap().entropy();
-                EntropyCoord::new(entropy, coord)
-            })
-            .collect::<BinaryHeap<_>>();
-
-        cs
+        self.entropy_heap.clear();
+        self.entropy_heap.extend(
+            self.grid
+                .enumerate()
+                .map(|(coord, cell)| EntropyCoord::new(cell.entropy(), coord)),
+        );
     }
 
     //
@@ -608,13 +1096,34 @@ impl CoreState {
     // to lower the chance of having ties
     //
     fn distribute_entropy_noise(&mut self) {
-        self.grid
-            .data
-            .par_iter_mut()
-            .for_each(|cell: &mut CoreCell| {
-                let mut rng = rand::thread_rng();
-                cell.entropy_noise = rng.gen();
-            });
+        let rng = &mut self.rng;
+        self.grid.data.iter_mut().for_each(|cell: &mut CoreCell| {
+            cell.entropy_noise = rng.gen();
+        });
+    }
+
+    //
+    // Restart the random choices from the given seed
+    //
+    pub fn reseed(&mut self, seed: Seed) {
+        self.seed = seed;
+        self.rng = make_rng(seed);
+    }
+
+    //
+    // Position of the neighbouring cell in the given direction,
+    // wrapping around the edges if the output is periodic
+    //
+    pub fn neighbour(&self, coord: Vector2, direction: Direction) -> Option<Vector2> {
+        let neighbour_coord = coord.neighbor(direction);
+
+        if self.periodic {
+            Some(self.grid.wrap(neighbour_coord))
+        } else if self.grid.valid_pos(neighbour_coord) {
+            Some(neighbour_coord)
+        } else {
+            None
+        }
     }
 
     //
@@ -646,7 +1155,7 @@ impl CoreState {
         let cell = self.grid.get_mut(coord).unwrap();
 
         let sample_index_chosen = {
-            if let Some(idx) = cell.choose_sample_index(&self.model) {
+            if let Some(idx) = cell.choose_sample_index(&self.model, &mut self.rng) {
                 idx
             } else {
                 return RunStatus::Failed;
@@ -663,6 +1172,15 @@ impl CoreState {
                 .push_back(RemovalUpdate { tile_index, coord });
         });
 
+        // Remember the decision so that it can be undone
+        if let Some(decision) = self.decisions.last_mut() {
+            decision.tile_index = Some(sample_index_chosen);
+            decision.trail.push(TrailEntry::Collapse {
+                coord,
+                removed: cell.possible.iter().collect(),
+            });
+        }
+
         // Remove ALL other possibilities
         cell.possible.clear();
 
@@ -703,82 +1221,416 @@ impl CoreState {
                     );
                 }
                 RunStatus::Succeeded => {
-                    self.propagate();
                     self.remaining_uncollapsed_cells -= 1;
+
+                    // Propagate the effects
+                    if self.propagate() == RunStatus::Failed {
+                        return (RunStatus::Failed, &self.grid);
+                    }
+                    self.after_collapse();
                 }
             }
-
-            // Propagate the effects
         }
         (RunStatus::Succeeded, &self.grid)
     }
 
     //
-    // Remove possibilities based on collapsed cell
+    // Search and kill loop which undoes the latest decisions
+    // on a contradiction instead of giving up on the whole run
     //
-    fn propagate(&mut self) {
-        while let Some(removal_update) = self.tile_removals.pop_front() {
-            'dir: for &direction in &ALL_DIRECTIONS {
-                // Propagate the effect to the neighbor in each direction
-                let neighbour_coord = removal_update.coord.neighbor(direction);
+    fn run_backtracking(&mut self) -> (RunStatus, &Grid2D<CoreCell>) {
+        let mut backtracks = 0;
+        self.decisions.clear();
 
-                if let Some(cell_to_update) = self.grid.get(neighbour_coord) {
-                    cell_to_update
-                } else {
-                    continue 'dir;
+        while self.remaining_uncollapsed_cells > 0 {
+            let next_coord = match self.choose_next_cell() {
+                Some(coord) => coord,
+                None => {
+                    return (RunStatus::Failed, &self.grid);
+                }
+            };
+
+            self.decisions.push(Decision::new(next_coord));
+
+            let mut status = self.collapse_cell_at(next_coord);
+            if status == RunStatus::Succeeded {
+                self.remaining_uncollapsed_cells -= 1;
+                status = self.propagate();
+            }
+            if status == RunStatus::Succeeded {
+                self.after_collapse();
+            }
+
+            while status == RunStatus::Failed {
+                backtracks += 1;
+                if backtracks > BACKTRACK_LIMIT {
+                    self.decisions.clear();
+                    return (RunStatus::Failed, &self.grid);
+                }
+
+                // Nothing left to undo, the state is unsolvable
+                let decision = match self.undo_last_decision() {
+                    Some(decision) => decision,
+                    None => return (RunStatus::Failed, &self.grid),
+                };
+
+                // The chosen tile lead to a contradiction, so it can't appear
+                // there. This is recorded as part of the previous decision.
+                status = match decision.tile_index {
+                    Some(tile_index) => self.ban(decision.coord, tile_index),
+                    None => RunStatus::Failed,
                 };
+            }
+        }
 
-                // Iterate over all the tiles which may appear in the neighbouring cell
-                // (in the direction of the current one)
-                for compatible_tile in
-                    self.model.adjacency_rule[removal_update.tile_index][direction.to_idx()].iter()
-                {
-                    let neighbor = self.grid.get_mut(neighbour_coord).unwrap();
+        self.decisions.clear();
+        (RunStatus::Succeeded, &self.grid)
+    }
 
-                    let count = {
-                        let count = &mut neighbor.tile_enabler_counts[compatible_tile].by_direction
-                            [direction.opposite().to_idx()];
+    //
+    // Revert every change made since the latest decision
+    //
+    fn undo_last_decision(&mut self) -> Option<Decision> {
+        let decision = self.decisions.pop()?;
+        self.tile_removals.clear();
+
+        for entry in decision.trail.iter().rev() {
+            match entry {
+                TrailEntry::Collapse { coord, removed } => {
+                    let cell = self.grid.get_mut(*coord).unwrap();
+                    cell.is_collpased = false;
+                    cell.possible.extend(removed.iter().copied());
+                    self.remaining_uncollapsed_cells += 1;
+                }
+                TrailEntry::Removal(update) => {
+                    self.grid
+                        .get_mut(update.coord)
+                        .unwrap()
+                        .restore_tile(update.tile_index, &self.model);
+                }
+                TrailEntry::EnablerDecrement {
+                    coord,
+                    tile_index,
+                    direction,
+                } => {
+                    self.grid.get_mut(*coord).unwrap().tile_enabler_counts[*tile_index]
+                        .by_direction[*direction] += 1;
+                }
+            }
 
-                        if *count == 0 {
-                            continue;
-                        }
+            // Restored cells have to be reachable from the heap again
+            let coord = match entry {
+                TrailEntry::Collapse { coord, .. } => *coord,
+                TrailEntry::Removal(update) => update.coord,
+                TrailEntry::EnablerDecrement { .. } => continue,
+            };
+            self.entropy_heap.push(EntropyCoord::new(
+                self.grid.get(coord).unwrap().entropy(),
+                coord,
+            ));
+        }
 
-                        *count -= 1;
-                        *count
-                    };
+        Some(decision)
+    }
 
-                    // If count is 0, we want to remove the tile from the neighbour
-                    if count == 0 {
-                        if neighbor.is_collpased
-                            || neighbor.tile_enabler_counts[compatible_tile]
-                                .by_direction
-                                .iter()
-                                .enumerate()
-                                .filter(|(dir, _)| dir != &(direction.opposite().to_idx()))
-                                .map(|(_, v)| v)
-                                .any(|&v| v == 0)
-                        {
-                            continue;
-                        }
-
-                        self.grid
-                            .get_mut(neighbour_coord)
-                            .unwrap()
-                            .remove_tile(compatible_tile, &self.model);
-
-                        let entropy = EntropyCoord {
-                            entropy: self.grid.get(neighbour_coord).unwrap().entropy(),
-                            coord: neighbour_coord,
-                        };
-                        self.entropy_heap.push(entropy);
+    //
+    // Limit the chosen borders to the patterns found on the same
+    // edges of the input and propagate, before anything is collapsed
+    //
+    pub fn constrain_edges(&mut self, edges: &[bool; 4]) -> RunStatus {
+        let (width, height) = (self.grid.width as i32, self.grid.height as i32);
 
-                        self.tile_removals.push_back(RemovalUpdate {
-                            tile_index: compatible_tile,
-                            coord: neighbour_coord,
+        for direction in ALL_DIRECTIONS {
+            if !edges[direction.to_idx()] {
+                continue;
+            }
+
+            let border: Vec<Vector2> = match direction {
+                Direction::Up => (0..width).map(|x| Vector2 { x, y: 0 }).collect(),
+                Direction::Down => (0..width).map(|x| Vector2 { x, y: height - 1 }).collect(),
+                Direction::Left => (0..height).map(|y| Vector2 { x: 0, y }).collect(),
+                Direction::Right => (0..height).map(|y| Vector2 { x: width - 1, y }).collect(),
+            };
+
+            let allowed = self.model.edge_patterns[direction.to_idx()].clone();
+            for coord in border {
+                if self.restrict(coord, &allowed) == RunStatus::Failed {
+                    return RunStatus::Failed;
+                }
+            }
+        }
+
+        self.propagate()
+    }
+
+    //
+    // Limit each of the given cells to its allowed tiles and propagate
+    //
+    pub fn constrain_cells(&mut self, cells: &[(Vector2, bit_set::BitSet)]) -> RunStatus {
+        for (coord, allowed) in cells {
+            if self.restrict(*coord, allowed) == RunStatus::Failed {
+                return RunStatus::Failed;
+            }
+        }
+
+        self.propagate()
+    }
+
+    //
+    // Remove every tile outside of `allowed` from the cell,
+    // the removals are left queued up for the next propagate
+    //
+    fn restrict(&mut self, coord: Vector2, allowed: &bit_set::BitSet) -> RunStatus {
+        let cell = self.grid.get_mut(coord).unwrap();
+
+        let banned: Vec<TileIndex> = cell.possible.difference(allowed).collect();
+        if banned.is_empty() {
+            return RunStatus::Succeeded;
+        }
+
+        for tile_index in banned {
+            cell.remove_tile(tile_index, &self.model);
+
+            let update = RemovalUpdate { tile_index, coord };
+            if let Some(decision) = self.decisions.last_mut() {
+                decision.trail.push(TrailEntry::Removal(update.clone()));
+            }
+            self.tile_removals.push_back(update);
+        }
+
+        if cell.has_no_possible_tiles() {
+            self.tile_removals.clear();
+            return RunStatus::Failed;
+        }
+
+        let entropy = EntropyCoord::new(cell.entropy(), coord);
+        self.entropy_heap.push(entropy);
+
+        RunStatus::Succeeded
+    }
+
+    //
+    // Remove a single tile from an uncollapsed cell and propagate it
+    //
+    fn ban(&mut self, coord: Vector2, tile_index: TileIndex) -> RunStatus {
+        let cell = self.grid.get_mut(coord).unwrap();
+
+        if !cell.possible.contains(tile_index) {
+            return RunStatus::Succeeded;
+        }
+
+        cell.remove_tile(tile_index, &self.model);
+
+        // Recorded before the emptiness check, a failed ban is undone too
+        let update = RemovalUpdate { tile_index, coord };
+        if let Some(decision) = self.decisions.last_mut() {
+            decision.trail.push(TrailEntry::Removal(update.clone()));
+        }
+        if cell.has_no_possible_tiles() {
+            return RunStatus::Failed;
+        }
+
+        let entropy = EntropyCoord::new(cell.entropy(), coord);
+        self.entropy_heap.push(entropy);
+        self.tile_removals.push_back(update);
+
+        self.propagate()
+    }
+
+    //
+    // Remove possibilities based on collapsed cell, fails once
+    // a wave of removals leaves a cell without possible tiles.
+    //
+    // Removals spread in waves. Large waves of Parallel are split up by
+    // the row of the cells they reach and the rows are worked through on
+    // their own, then joined back in order, so the result doesn't depend
+    // on the number of threads. Every cell takes its removals in the same
+    // order either way, so Sequential removes the same tiles.
+    //
+    fn propagate(&mut self) -> RunStatus {
+        let width = self.grid.width;
+        let record = !self.decisions.is_empty();
+        let mut wave: Vec<RemovalUpdate> = self.tile_removals.drain(..).collect();
+        let mut incoming = Vec::new();
+        let mut next_wave = RowWave::default();
+
+        while !wave.is_empty() {
+            // Hand every removal to each neighbour it affects
+            incoming.clear();
+            for removal_update in &wave {
+                for &direction in &ALL_DIRECTIONS {
+                    if let Some(coord) = self.neighbour(removal_update.coord, direction) {
+                        incoming.push(Incoming {
+                            coord,
+                            tile_index: removal_update.tile_index,
+                            direction,
+                            order: incoming.len(),
                         });
                     }
                 }
             }
+
+            let model = &self.model;
+            if self.propagation == Propagation::Parallel && wave.len() >= PARALLEL_WAVE {
+                incoming.sort_by_key(|item| item.coord.y);
+
+                // Cut the grid into the rows which have work to do
+                let mut rows = Vec::new();
+                let mut rest: &mut [CoreCell] = &mut self.grid.data;
+                let mut offset = 0;
+                for items in incoming.chunk_by(|a, b| a.coord.y == b.coord.y) {
+                    let start = items[0].coord.y as usize * width;
+                    let (_, tail) = std::mem::take(&mut rest).split_at_mut(start - offset);
+                    let (row, tail) = tail.split_at_mut(width);
+                    rows.push((items[0].coord.y as usize, row, items));
+                    rest = tail;
+                    offset = start + width;
+                }
+
+                let row_waves: Vec<RowWave> = rows
+                    .into_par_iter()
+                    .map(|(y, row, items)| {
+                        let mut row_wave = RowWave::default();
+                        propagate_cells(row, y, width, items, model, record, &mut row_wave);
+                        row_wave
+                    })
+                    .collect();
+
+                // The next wave goes back to the order of the incoming removals,
+                // which is the order the sequential branch finds it in
+                let mut removals = Vec::new();
+                for mut row_wave in row_waves {
+                    removals.extend(row_wave.orders.drain(..).zip(row_wave.removals.drain(..)));
+                    next_wave.trail.append(&mut row_wave.trail);
+                    next_wave.entropies.append(&mut row_wave.entropies);
+                    next_wave.failed |= row_wave.failed;
+                }
+                removals.sort_by_key(|(order, _)| *order);
+                next_wave
+                    .removals
+                    .extend(removals.into_iter().map(|(_, removal)| removal));
+            } else {
+                // Small waves aren't worth sorting into rows
+                let grid = &mut self.grid.data;
+                propagate_cells(grid, 0, width, &incoming, model, record, &mut next_wave);
+                next_wave.orders.clear();
+            }
+
+            if let Some(decision) = self.decisions.last_mut() {
+                decision.trail.append(&mut next_wave.trail);
+            }
+            self.entropy_heap.extend(next_wave.entropies.drain(..));
+
+            wave.clear();
+            std::mem::swap(&mut wave, &mut next_wave.removals);
+
+            let failed = std::mem::take(&mut next_wave.failed);
+            if failed {
+                return RunStatus::Failed;
+            }
+        }
+
+        RunStatus::Succeeded
+    }
+}
+
+// A removed tile reaching one of the neighbours of its cell,
+// `direction` points from the cell towards the neighbour
+struct Incoming {
+    coord: Vector2,
+    tile_index: TileIndex,
+    direction: Direction,
+    // Position in the wave before it is sorted into rows
+    order: usize,
+}
+
+// Everything a single row produced during one wave of propagate
+#[derive(Default)]
+struct RowWave {
+    removals: Vec<RemovalUpdate>,
+    // Order of the incoming removal behind each of `removals`
+    orders: Vec<usize>,
+    trail: Vec<TrailEntry>,
+    entropies: Vec<EntropyCoord>,
+    failed: bool,
+}
+
+//
+// Take away the enablers of the incoming removals from `cells`, the
+// rows of the grid starting at `first_row`. Tiles which run out of
+// enablers are removed in turn. A row stops at the first cell left
+// without tiles, the others go on, wherever the rows are handled.
+//
+fn propagate_cells(
+    cells: &mut [CoreCell],
+    first_row: usize,
+    width: usize,
+    incoming: &[Incoming],
+    model: &Model,
+    record: bool,
+    row_wave: &mut RowWave,
+) {
+    let mut failed_rows = Vec::new();
+    for item in incoming {
+        if failed_rows.contains(&item.coord.y) {
+            continue;
+        }
+
+        let neighbor =
+            &mut cells[(item.coord.y as usize - first_row) * width + item.coord.x as usize];
+        let direction = item.direction.opposite().to_idx();
+
+        // Iterate over all the tiles which may appear in the neighbouring cell
+        // (in the direction of the current one)
+        for compatible_tile in model.adjacency_rule[item.tile_index][item.direction.to_idx()].iter()
+        {
+            let count = {
+                let count =
+                    &mut neighbor.tile_enabler_counts[compatible_tile].by_direction[direction];
+
+                if *count == 0 {
+                    continue;
+                }
+
+                *count -= 1;
+                *count
+            };
+
+            if record {
+                row_wave.trail.push(TrailEntry::EnablerDecrement {
+                    coord: item.coord,
+                    tile_index: compatible_tile,
+                    direction,
+                });
+            }
+
+            // If count is 0, we want to remove the tile from the neighbour
+            if count > 0 || neighbor.is_collpased || !neighbor.possible.contains(compatible_tile) {
+                continue;
+            }
+
+            neighbor.remove_tile(compatible_tile, model);
+
+            let update = RemovalUpdate {
+                tile_index: compatible_tile,
+                coord: item.coord,
+            };
+
+            if record {
+                row_wave.trail.push(TrailEntry::Removal(update.clone()));
+            }
+
+            if neighbor.has_no_possible_tiles() {
+                row_wave.failed = true;
+                failed_rows.push(item.coord.y);
+                break;
+            }
+
+            row_wave
+                .entropies
+                .push(EntropyCoord::new(neighbor.entropy(), item.coord));
+            row_wave.removals.push(update);
+            row_wave.orders.push(item.order);
         }
     }
 }
@@ -786,10 +1638,22 @@ impl CoreState {
 #[cfg(test)]
 mod tests {
 
-    use crate::{data::direction::Direction, model::Model};
+    use std::sync::Arc;
+
+    use crate::data::colour::{opaque, Rgba};
+    use crate::data::direction::Direction;
+    use crate::data::vector2::Vector2;
+    use crate::error::Error;
+    use crate::image_reader::Image;
+    use crate::model::{Model, ModelOptions};
+    use crate::validate::{validate, validate_image};
 
-    use super::{CoreCell, CoreState};
+    use super::{
+        runs, strip_mask, CoreCell, CoreState, Decision, Propagation, RunStatus, SolveOptions,
+        Strategy,
+    };
     fn find_sample_idx(model: &Model, sample: Vec<[u8; 3]>) -> Option<usize> {
+        let sample: Vec<_> = sample.into_iter().map(opaque).collect();
         model
             .samples
             .clone()
@@ -808,7 +1672,8 @@ mod tests {
     #[test]
     fn test_removal_entropy() {
         for _ in 0..10 {
-            let mut cs = CoreState::new("samples/Flowers.png", 3, 10, 10, false);
+            let mut cs =
+                CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 10, 10, 0).unwrap();
 
             // For Sample ID
             let target_sample = &cs.model.samples[0];
@@ -824,8 +1689,14 @@ mod tests {
                 .collect::<Vec<_>>();
 
             // Cached Version
-            let mut cs2 = CoreState::new("samples/Flowers.png", 3, 10, 10, false);
-            let sample_id = find_sample_idx(&cs2.model, target_sample.region.data.clone()).unwrap();
+            let mut cs2 =
+                CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 10, 10, 0).unwrap();
+            let sample_id = cs2
+                .model
+                .samples
+                .iter()
+                .position(|sample| sample == target_sample)
+                .unwrap();
 
             assert_eq!(
                 &cs2.model.samples[sample_id].region.data,
@@ -833,9 +1704,7 @@ mod tests {
             );
 
             (0..cs2.model.size()).for_each(|idx| {
-                if idx == sample_id {
-                    return;
-                } else {
+                if idx != sample_id {
                     cs2.grid.data[0].remove_tile(idx, &cs2.model)
                 }
             });
@@ -864,7 +1733,8 @@ mod tests {
     //
     #[test]
     fn test_binary_heap() {
-        let mut cs = CoreState::new("samples/Flowers.png", 3, 50, 50, false);
+        let mut cs =
+            CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 50, 50, 0).unwrap();
 
         for _ in 0..cs.grid.size() {
             let least_entropy = &cs.entropy_heap.peek();
@@ -875,7 +1745,7 @@ mod tests {
 
     #[test]
     fn test_basic_collapse() {
-        let mut cs = CoreState::new("samples/Flowers.png", 3, 3, 3, false);
+        let mut cs = CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 3, 3, 0).unwrap();
 
         // Check that the same collapsed cell is never visited again
         let mut positions_collapsed = bit_set::BitSet::new();
@@ -912,7 +1782,7 @@ mod tests {
 
     #[test]
     fn test_enablers_count() {
-        let cs = CoreState::new("samples/Flowers.png", 3, 5, 5, false);
+        let cs = CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 5, 5, 0).unwrap();
 
         let init_enablers_count = cs.model.get_initial_tile_enabler_counts();
 
@@ -923,7 +1793,8 @@ mod tests {
 
     #[test]
     fn test_enablers_count_specific() {
-        let cs = CoreState::new("samples/ProcessExample.png", 3, 5, 5, false);
+        let cs =
+            CoreState::new("samples/ProcessExample.png", &ModelOptions::new(3), 5, 5, 0).unwrap();
 
         let sample_1 = find_sample_idx(
             &cs.model,
@@ -964,7 +1835,8 @@ mod tests {
 
     #[test]
     fn test_enablers_count_specific_2() {
-        let cs = CoreState::new("samples/ProcessExample.png", 3, 5, 5, false);
+        let cs =
+            CoreState::new("samples/ProcessExample.png", &ModelOptions::new(3), 5, 5, 0).unwrap();
 
         let sample_1 = find_sample_idx(
             &cs.model,
@@ -1004,4 +1876,406 @@ mod tests {
 
         assert!(cs.model.adjacency_rule[sample_1][Direction::Down.to_idx()].contains(sample_1));
     }
+
+    #[test]
+    fn test_undo_decision() {
+        let mut cs =
+            CoreState::new("samples/ProcessExample.png", &ModelOptions::new(3), 8, 8, 0).unwrap();
+        let before = cs.clone();
+
+        let coord = cs.choose_next_cell().unwrap();
+        cs.decisions.push(Decision::new(coord));
+        assert!(cs.collapse_cell_at(coord) == RunStatus::Succeeded);
+        cs.remaining_uncollapsed_cells -= 1;
+        cs.propagate();
+
+        let decision = cs.undo_last_decision().unwrap();
+        assert_eq!(decision.coord, coord);
+        assert!(decision.tile_index.is_some());
+
+        assert_same_cells(&cs, &before);
+    }
+
+    #[test]
+    fn test_undo_failed_ban() {
+        let mut cs =
+            CoreState::new("samples/ProcessExample.png", &ModelOptions::new(3), 8, 8, 0).unwrap();
+        let before = cs.clone();
+
+        // Outer decision
+        let outer = cs.choose_next_cell().unwrap();
+        cs.decisions.push(Decision::new(outer));
+        assert!(cs.collapse_cell_at(outer) == RunStatus::Succeeded);
+        cs.remaining_uncollapsed_cells -= 1;
+        assert!(cs.propagate() == RunStatus::Succeeded);
+
+        // Inner decision, undone straight away
+        let inner = cs.choose_next_cell().unwrap();
+        cs.decisions.push(Decision::new(inner));
+        assert!(cs.collapse_cell_at(inner) == RunStatus::Succeeded);
+        cs.remaining_uncollapsed_cells -= 1;
+        cs.propagate();
+        let tile_index = cs.undo_last_decision().unwrap().tile_index.unwrap();
+
+        // Banning the last tile left empties the cell
+        let only = bit_set::BitSet::from_iter([tile_index]);
+        assert!(cs.restrict(inner, &only) == RunStatus::Succeeded);
+        assert!(cs.ban(inner, tile_index) == RunStatus::Failed);
+
+        // Undoing the outer decision restores the banned tile as well
+        assert_eq!(cs.undo_last_decision().unwrap().coord, outer);
+        assert_same_cells(&cs, &before);
+    }
+
+    fn assert_same_cells(cs: &CoreState, before: &CoreState) {
+        assert_eq!(
+            cs.remaining_uncollapsed_cells,
+            before.remaining_uncollapsed_cells
+        );
+        cs.grid
+            .data
+            .iter()
+            .zip(before.grid.data.iter())
+            .for_each(|(cell, old)| {
+                assert_eq!(cell.possible, old.possible);
+                assert_eq!(cell.is_collpased, old.is_collpased);
+                assert_eq!(cell.tile_enabler_counts, old.tile_enabler_counts);
+                assert_eq!(
+                    cell.sum_of_possible_tile_weights,
+                    old.sum_of_possible_tile_weights
+                );
+                assert!(
+                    (cell.sum_of_possible_tile_weight_log_weights
+                        - old.sum_of_possible_tile_weight_log_weights)
+                        .abs()
+                        < 1e-3
+                );
+            });
+    }
+
+    #[test]
+    fn test_backtracking_run() {
+        let mut cs = CoreState::new("samples/rooms.png", &ModelOptions::new(3), 24, 24, 0).unwrap();
+
+        let (status, grid) = cs.run_backtracking();
+        assert!(status == RunStatus::Succeeded);
+        let grid = grid.clone();
+
+        // Every cell is collapsed and agrees with its right and bottom neighbour
+        for (coord, cell) in grid.enumerate() {
+            let tile = cell.get_the_only_possible_tile_index().unwrap();
+
+            for direction in [Direction::Right, Direction::Down] {
+                if let Some(neighbor) = grid.get(coord.neighbor(direction)) {
+                    let other = neighbor.get_the_only_possible_tile_index().unwrap();
+                    assert!(cs.model.adjacency_rule[tile][direction.to_idx()].contains(other));
+                }
+            }
+        }
+        assert!(cs.decisions.is_empty());
+    }
+
+    #[test]
+    fn test_seeded_run() {
+        let run = |seed| {
+            let mut cs =
+                CoreState::new("samples/Flowers.png", &ModelOptions::new(3), 12, 12, seed).unwrap();
+            let (status, grid) = cs.run();
+            assert!(status == RunStatus::Succeeded);
+            grid.data
+                .iter()
+                .map(|cell| cell.get_the_only_possible_tile_index())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_reset() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let tiles = |cs: &mut CoreState| {
+            let (status, grid) = cs.run();
+            assert!(status == RunStatus::Succeeded);
+            grid.data
+                .iter()
+                .map(|cell| cell.get_the_only_possible_tile_index())
+                .collect::<Vec<_>>()
+        };
+
+        // A used state starts over exactly like a fresh one
+        let mut cs = CoreState::from_model(Arc::clone(&model), 12, 12, 7);
+        tiles(&mut cs);
+        cs.reset(8);
+        assert_eq!(cs.remaining_uncollapsed_cells, 144);
+        assert_eq!(cs.entropy_heap.len(), 144);
+        assert_eq!(
+            tiles(&mut cs),
+            tiles(&mut CoreState::from_model(Arc::clone(&model), 12, 12, 8))
+        );
+
+        // The model itself is never copied
+        assert!(Arc::ptr_eq(&cs.model, &model));
+    }
+
+    #[test]
+    fn test_seeded_par_process() {
+        let process = |threads, strategy| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| {
+                    let options = SolveOptions {
+                        strategy,
+                        seed: 1234,
+                        ..SolveOptions::default()
+                    };
+                    CoreState::par_process(
+                        "samples/Flowers.png",
+                        &ModelOptions::new(3),
+                        32,
+                        32,
+                        &options,
+                    )
+                    .unwrap()
+                })
+        };
+
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        for strategy in [Strategy::Restart, Strategy::Backtrack] {
+            let pixels = process(1, strategy);
+            assert_eq!(pixels, process(4, strategy));
+
+            // The seams between the blocks hold up as well
+            let output = Image {
+                width: 32,
+                height: 32,
+                pixels,
+            };
+            assert_eq!(validate_image(&model, &output, false).unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_parallel_propagation() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let solve = |threads, propagation, strategy| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| {
+                    // The edges remove far more than PARALLEL_WAVE tiles at once
+                    let options = SolveOptions {
+                        strategy,
+                        seed: 77,
+                        edges: [true; 4],
+                        blocks: (1, 1),
+                        propagation,
+                        ..SolveOptions::default()
+                    };
+                    CoreState::par_solve(&model, 48, 48, &options).unwrap()
+                })
+        };
+
+        for strategy in [Strategy::Restart, Strategy::Backtrack] {
+            let sequential = solve(1, Propagation::Sequential, strategy);
+            assert_eq!(sequential, solve(1, Propagation::Parallel, strategy));
+            assert_eq!(sequential, solve(4, Propagation::Parallel, strategy));
+        }
+    }
+
+    #[test]
+    fn test_recording() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let solve = |threads, strategy, snapshot_every| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| {
+                    let options = SolveOptions {
+                        strategy,
+                        seed: 3,
+                        snapshot_every,
+                        ..SolveOptions::default()
+                    };
+                    CoreState::par_solve(&model, 24, 20, &options).unwrap()
+                })
+        };
+
+        for strategy in [Strategy::Restart, Strategy::Backtrack] {
+            let plain = solve(1, strategy, None);
+            let recorded = solve(1, strategy, Some(10));
+            assert!(plain.frames.is_empty());
+
+            // Recording doesn't change the output, and the frames
+            // don't depend on the blocks finishing in any order
+            assert_eq!(recorded.tiles, plain.tiles);
+            assert_eq!(recorded, solve(4, strategy, Some(10)));
+
+            let frames = &recorded.frames;
+            assert!(frames.len() > 24 * 20 / 10 / 4);
+            assert!(frames.iter().all(|f| (f.width, f.height) == (24, 20)));
+            assert_eq!(*frames.last().unwrap(), model.render(&recorded.tiles));
+            assert_ne!(frames[0], *frames.last().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_output_sizes() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+
+        assert!(matches!(
+            CoreState::par_solve(&model, 0, 32, &SolveOptions::default()),
+            Err(Error::OutputTooSmall { .. })
+        ));
+
+        // Tiny, odd and non-square outputs are solved all the way,
+        // any cell left out of the strips and blocks is an error
+        let sizes = [(1, 1), (1, 9), (2, 2), (3, 5), (6, 3), (7, 13), (33, 20)];
+        for (width, height) in sizes {
+            for blocks in [(1, 1), (2, 2), (3, 4)] {
+                let options = SolveOptions {
+                    seed: 21,
+                    blocks,
+                    ..SolveOptions::default()
+                };
+                let tiles = CoreState::par_solve(&model, width, height, &options)
+                    .unwrap()
+                    .tiles;
+                assert_eq!((tiles.width, tiles.height), (width, height));
+                assert_eq!(validate(&model, &tiles, false), vec![]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_periodic_output() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let options = SolveOptions {
+            seed: 11,
+            periodic: true,
+            ..SolveOptions::default()
+        };
+
+        // Small tori are mostly seam strips, they still have to work
+        assert!(CoreState::par_solve(&model, 5, 7, &options).is_ok());
+
+        let tiles = CoreState::par_solve(&model, 24, 20, &options)
+            .unwrap()
+            .tiles;
+
+        // Every pair of neighbours agrees, including the ones across the seams
+        assert_eq!(validate(&model, &tiles, true), vec![]);
+    }
+
+    #[test]
+    fn test_edge_constraints() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let img = image::open("samples/Flowers.png").unwrap();
+        let input = Image::from_dynamic(&img);
+        let ground: Vec<Rgba> = (0..input.width as i32)
+            .map(|x| {
+                input.at(Vector2 {
+                    x,
+                    y: input.height as i32 - 1,
+                })
+            })
+            .collect();
+
+        // The bottom row of the output is drawn from the bottom row of the input
+        let mut options = SolveOptions::default();
+        options.edges[Direction::Down.to_idx()] = true;
+        for seed in 0..5 {
+            options.seed = seed;
+            let tiles = CoreState::par_solve(&model, 20, 20, &options)
+                .unwrap()
+                .tiles;
+            let pixels = model.render(&tiles);
+            for x in 0..pixels.width {
+                let colour = pixels.data[(pixels.height - 1) * pixels.width + x];
+                assert!(ground.contains(&colour));
+            }
+        }
+
+        // A border with nothing allowed on it can never be solved,
+        // the partial render shows where it gave up
+        let mut model = model;
+        Arc::make_mut(&mut model).edge_patterns[Direction::Down.to_idx()].clear();
+        match CoreState::par_solve(&model, 20, 20, &options) {
+            Err(Error::Unsatisfiable { partial, .. }) => {
+                assert_eq!((partial.width, partial.height), (20, 20));
+                assert!(partial.data.contains(&crate::model::CONTRADICTION));
+            }
+            other => panic!("expected Unsatisfiable, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_strip_mask() {
+        // One strip of 3 centred on the middle
+        let mask = strip_mask(10, 2, 3, false);
+        assert_eq!(runs(&mask), vec![(0, 4), (7, 3)]);
+
+        // The seam strip wraps around both ends
+        let mask = strip_mask(12, 3, 3, true);
+        assert_eq!(runs(&mask), vec![(2, 1), (6, 1), (10, 1)]);
+        assert!(mask[0] && mask[11]);
+
+        // A single block has no strips unless it wraps
+        assert_eq!(runs(&strip_mask(5, 1, 3, false)), vec![(0, 5)]);
+        assert_eq!(runs(&strip_mask(5, 1, 3, true)), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn test_block_split() {
+        let model = Arc::new(Model::create("samples/Flowers.png", &ModelOptions::new(3)).unwrap());
+        let options = SolveOptions {
+            seed: 8,
+            blocks: (3, 2),
+            ..SolveOptions::default()
+        };
+
+        let mut state = CoreState::from_model(model.clone(), 31, 17, options.seed);
+        let blocks = state.split(options.blocks).unwrap();
+        assert_eq!(blocks.len(), 6);
+
+        // Blocks and strips cover the output exactly once
+        let mut covered = vec![0; 31 * 17];
+        for (coord, cell) in state.grid.enumerate() {
+            if cell.is_collpased {
+                covered[state.grid.idx(coord).unwrap()] += 1;
+            }
+        }
+        for block in &blocks {
+            for (coord, cell) in block.state.grid.enumerate() {
+                let pos = state.grid.idx(block.origin + coord).unwrap();
+                if cell.is_collpased {
+                    // Halo cells are the strip cells around the block
+                    let strip = state.grid.data[pos].get_the_only_possible_tile_index();
+                    assert_eq!(cell.get_the_only_possible_tile_index(), strip);
+                } else {
+                    covered[pos] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+
+        // The top middle block has a halo everywhere but on the output edge
+        let middle = &blocks[1].state.grid;
+        let column = |x: i32| (0..middle.height as i32).map(move |y| Vector2 { x, y });
+        let collapsed = |pos| middle.get(pos).unwrap().is_collpased;
+        assert!(column(0).all(collapsed));
+        assert!(column(middle.width as i32 - 1).all(collapsed));
+        assert!(!column(1).all(collapsed));
+        assert!(!collapsed(Vector2 { x: 1, y: 0 }));
+
+        let tiles = CoreState::par_solve(&model, 31, 17, &options)
+            .unwrap()
+            .tiles;
+        assert_eq!(validate(&model, &tiles, false), vec![]);
+    }
 }
@@ -1,9 +1,30 @@
-use image::Rgba;
+pub type Rgba = [u8; 4];
 
-pub type Rgb = [u8; 3];
+pub const BLACK: Rgba = [0, 0, 0, 255];
 
-pub const BLACK: Rgb = [0, 0, 0];
+// Every fully transparent pixel becomes this one
+// when transparent pixels are treated as equal
+pub const TRANSPARENT: Rgba = [0, 0, 0, 0];
 
-pub fn make_rgb(rgb: &Rgba<u8>) -> Rgb {
-    rgb.0[0..3].try_into().expect("RGB: Incorrect format")
+pub fn make_rgba(rgba: &image::Rgba<u8>) -> Rgba {
+    rgba.0
+}
+
+//
+// Fully opaque colour from its red, green and blue channels
+//
+pub fn opaque([r, g, b]: [u8; 3]) -> Rgba {
+    [r, g, b, 255]
+}
+
+//
+// Fully transparent pixels only differ by colour channels nobody
+// gets to see, map them all to the same pixel
+//
+pub fn merge_transparent(rgba: Rgba) -> Rgba {
+    if rgba[3] == 0 {
+        TRANSPARENT
+    } else {
+        rgba
+    }
 }
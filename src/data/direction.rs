@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down,
@@ -16,6 +16,25 @@ impl Direction {
         }
     }
 
+    // Direction after rotating counter-clockwise by 90 degrees
+    pub fn rotate_ccw(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    // Direction after mirroring horizontally
+    pub fn reflect_x(self) -> Direction {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            other => other,
+        }
+    }
+
     pub fn opposite(self) -> Direction {
         match self {
             Direction::Up => Direction::Down,
@@ -1,10 +1,12 @@
 use super::vector2::Vector2;
+use crate::error::{Error, Result};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 // Note: The attributes are public
 //
 #[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Grid2D<T> {
     pub width: usize,
     pub height: usize,
@@ -41,9 +43,10 @@ impl<T: Clone + Send + Sync> Grid2D<T> {
         }
     }
 
-    pub fn set(&mut self, pos: Vector2, item: T) {
-        let idx = self.idx(pos).unwrap();
+    pub fn set(&mut self, pos: Vector2, item: T) -> Result<()> {
+        let idx = self.idx(pos).ok_or(Error::OutOfBounds(pos))?;
         self.data[idx] = item;
+        Ok(())
     }
 
     // Given an index, return the coord
@@ -94,6 +97,15 @@ impl<T: Clone + Send + Sync> Grid2D<T> {
         }
     }
 
+    // Wrap the position around the edges
+    // as if the grid was a torus
+    pub fn wrap(&self, pos: Vector2) -> Vector2 {
+        Vector2 {
+            x: pos.x.rem_euclid(self.width as i32),
+            y: pos.y.rem_euclid(self.height as i32),
+        }
+    }
+
     // Ensure that the position is valid (not out of bounds)
     #[allow(dead_code)]
     pub fn valid_pos(&self, pos: Vector2) -> bool {
@@ -1,4 +1,6 @@
-use super::colour::Rgb;
+use serde::{Deserialize, Serialize};
+
+use super::colour::{self, Rgba};
 use super::direction::Direction;
 use super::grid2d::Grid2D;
 use super::vector2::Vector2;
@@ -6,9 +8,9 @@ use super::vector2::Vector2;
 //
 // Sample Container
 //
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Sample {
-    pub region: Grid2D<Rgb>,
+    pub region: Grid2D<Rgba>,
 }
 
 pub type SampleID = usize;
@@ -26,17 +28,60 @@ impl Sample {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn get_rotations(&self) -> Vec<Sample> {
-        vec![
-            self.clone(),
-            self.rev_sample(),
-            self.transpose_sample(),
-            self.rev_sample_y(),
-        ]
+    //
+    // Build a new width x height sample where every pixel
+    // is read from `source(x, y)` of this sample
+    //
+    fn remap(
+        &self,
+        width: usize,
+        height: usize,
+        source: impl Fn(usize, usize) -> (usize, usize),
+    ) -> Sample {
+        Sample {
+            region: Grid2D {
+                width,
+                height,
+                data: (0..width * height)
+                    .map(|idx| {
+                        let (x, y) = source(idx % width, idx / width);
+                        self.region.data[y * self.region.width + x]
+                    })
+                    .collect(),
+            },
+        }
     }
 
-    #[allow(dead_code)]
+    //
+    // The first `symmetry` elements of the dihedral group applied to the
+    // sample, alternating reflections and counter-clockwise rotations:
+    //
+    // identity, reflect, rotate, reflect(rotate), rotate², ...
+    //
+    // 1 keeps the sample as is and 8 yields every rotation and reflection.
+    //
+    pub fn symmetries(&self, symmetry: usize) -> Vec<Sample> {
+        let mut variants = Vec::with_capacity(8);
+        let mut rotated = self.clone();
+        for _ in 0..4 {
+            let reflected = rotated.reflect_x();
+            let next = rotated.rotate_ccw();
+            variants.push(rotated);
+            variants.push(reflected);
+            rotated = next;
+        }
+        variants.truncate(symmetry.clamp(1, 8));
+
+        // Quarter turns lay a non-square sample on its side,
+        // it can't be placed in the same grid as the others
+        let shape = (self.region.width, self.region.height);
+        variants.retain(|variant| (variant.region.width, variant.region.height) == shape);
+        variants
+    }
+
+    //
+    // Rotate by 180 degrees
+    //
     pub fn rev_sample(&self) -> Sample {
         let rev_grid_data = self.region.clone().data.into_iter().rev().collect();
         Sample {
@@ -48,9 +93,11 @@ impl Sample {
         }
     }
 
-    #[allow(dead_code)]
+    //
+    // Reflect along the main diagonal (rows become columns)
+    //
     pub fn transpose_sample(&self) -> Sample {
-        let mut transposed = vec![[0u8, 0u8, 0u8]; self.region.size()];
+        let mut transposed = vec![colour::TRANSPARENT; self.region.size()];
         transpose::transpose(
             &self.region.data,
             &mut transposed,
@@ -59,65 +106,54 @@ impl Sample {
         );
         Sample {
             region: Grid2D {
-                width: self.region.width,
-                height: self.region.height,
+                width: self.region.height,
+                height: self.region.width,
                 data: transposed,
             },
         }
     }
 
-    #[allow(dead_code)]
-    pub fn rev_sample_y(&self) -> Sample {
-        let transposed = self.clone().transpose_sample();
-        let reversed_y = transposed
-            .region
-            .data
-            .iter()
-            .map(|[r, g, b]| [*b, *g, *r])
-            .collect();
-        Sample {
-            region: Grid2D {
-                width: self.region.width,
-                height: self.region.height,
-                data: reversed_y,
-            },
-        }
+    //
+    // Reflect along the anti-diagonal
+    //
+    pub fn anti_transpose_sample(&self) -> Sample {
+        self.transpose_sample().rev_sample()
     }
 
-    pub fn rotate(&self) -> Vec<Sample> {
-        let mut rotations = vec![self.clone()];
-        let mut rotate = self.clone();
-        for _n in 0..3 {
-            rotate = rotate.rotate_90();
-            rotations.push(rotate.clone());
-        }
-        rotations
+    //
+    // Mirror the sample vertically (top becomes bottom)
+    //
+    pub fn rev_sample_y(&self) -> Sample {
+        let height = self.region.height;
+        self.remap(self.region.width, height, |x, y| (x, height - 1 - y))
     }
 
+    //
+    // Rotate clockwise by 90 degrees
+    //
     pub fn rotate_90(&self) -> Sample {
-        let data = self.clone().transpose_sample().region.data;
-        let mut rotated = data;
-        rotated = (0..self.region.width)
-            .flat_map(|row| {
-                rotated[(row * self.region.height)..(row * self.region.height) + self.region.height]
-                    .iter()
-                    .copied()
-                    .rev()
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+        let height = self.region.height;
+        self.remap(height, self.region.width, |x, y| (y, height - 1 - x))
+    }
 
-        Sample {
-            region: Grid2D {
-                width: self.region.width,
-                height: self.region.height,
-                data: rotated,
-            },
-        }
+    //
+    // Rotate counter-clockwise by 90 degrees
+    //
+    pub fn rotate_ccw(&self) -> Sample {
+        let width = self.region.width;
+        self.remap(self.region.height, width, |x, y| (width - 1 - y, x))
+    }
+
+    //
+    // Mirror the sample horizontally (left becomes right)
+    //
+    pub fn reflect_x(&self) -> Sample {
+        let width = self.region.width;
+        self.remap(width, self.region.height, |x, y| (width - 1 - x, y))
     }
 
     #[allow(dead_code)]
-    pub fn get_top_left_pixel(&self) -> Rgb {
+    pub fn get_top_left_pixel(&self) -> Rgba {
         *self.region.get(Vector2 { x: 0, y: 0 }).unwrap()
     }
 
@@ -132,10 +168,25 @@ impl Sample {
     //
     // Retrieve colour from Vector2 position
     //
-    pub fn at(&self, at: Vector2) -> Rgb {
+    pub fn at(&self, at: Vector2) -> Rgba {
         *self.region.get(at).unwrap()
     }
 
+    //
+    // The part of the sample covered by a neighbour placed one pixel
+    // away in `direction`, two samples are compatible exactly when
+    // a.overlap(direction) == b.overlap(direction.opposite())
+    //
+    pub fn overlap(&self, direction: Direction) -> Sample {
+        let (width, height) = (self.region.width, self.region.height);
+        match direction {
+            Direction::Up => self.remap(width, height - 1, |x, y| (x, y)),
+            Direction::Down => self.remap(width, height - 1, |x, y| (x, y + 1)),
+            Direction::Left => self.remap(width - 1, height, |x, y| (x, y)),
+            Direction::Right => self.remap(width - 1, height, |x, y| (x + 1, y)),
+        }
+    }
+
     pub fn compatible(&self, other: &Sample, direction: Direction) -> bool {
         if other.region.width != self.region.width || other.region.height != self.region.height {
             return false;
@@ -184,13 +235,20 @@ impl Sample {
 mod tests {
     use super::*;
 
+    fn opaque(rgb: &[[u8; 3]]) -> Vec<Rgba> {
+        rgb.iter()
+            .copied()
+            .map(crate::data::colour::opaque)
+            .collect()
+    }
+
     #[test]
     fn test_rotate() {
         let s1: Sample = Sample {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
@@ -200,15 +258,14 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         let s2: Sample = Sample {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [136, 136, 255],
                     [0, 0, 0],
                     [0, 0, 0],
@@ -218,15 +275,14 @@ mod tests {
                     [136, 136, 255],
                     [0, 0, 0],
                     [0, 0, 0],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         let s3: Sample = Sample {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
@@ -236,8 +292,7 @@ mod tests {
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         assert_eq!(s1.rotate_90(), s2);
@@ -250,7 +305,7 @@ mod tests {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
@@ -260,8 +315,7 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
 
@@ -269,7 +323,7 @@ mod tests {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [136, 136, 255],
                     [0, 0, 0],
                     [0, 0, 0],
@@ -279,8 +333,7 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         assert!(s1.compatible(&s2, Direction::Right));
@@ -292,7 +345,7 @@ mod tests {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [0, 0, 0],
                     [0, 0, 0],
                     [136, 136, 255],
@@ -302,15 +355,14 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         let s2 = Sample {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
@@ -320,8 +372,7 @@ mod tests {
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
-                ]
-                .to_vec(),
+                ]),
             },
         };
         assert!(!&s1.compatible(&s2, Direction::Up));
@@ -335,7 +386,7 @@ mod tests {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
@@ -345,8 +396,7 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
 
@@ -354,7 +404,7 @@ mod tests {
             region: Grid2D {
                 width: 3,
                 height: 3,
-                data: [
+                data: opaque(&[
                     [136, 136, 255],
                     [0, 0, 0],
                     [0, 0, 0],
@@ -364,11 +414,110 @@ mod tests {
                     [136, 136, 255],
                     [136, 136, 255],
                     [136, 136, 255],
-                ]
-                .to_vec(),
+                ]),
             },
         };
-        assert_eq!(s2.get_top_left_pixel(), [136, 136, 255]);
-        assert_eq!(s1.get_top_left_pixel(), [0, 0, 0]);
+        assert_eq!(s2.get_top_left_pixel(), [136, 136, 255, 255]);
+        assert_eq!(s1.get_top_left_pixel(), [0, 0, 0, 255]);
+    }
+
+    // Sample with the given width where every
+    // pixel only differs in the red channel
+    fn make_sample(width: usize, pixels: &[u8]) -> Sample {
+        Sample {
+            region: Grid2D {
+                width,
+                height: pixels.len() / width,
+                data: pixels.iter().map(|&v| [v, 0, 0, 255]).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_transforms() {
+        // a b c
+        // d e f
+        // g h i
+        let s = make_sample(3, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(s.reflect_x(), make_sample(3, &[3, 2, 1, 6, 5, 4, 9, 8, 7]));
+        assert_eq!(
+            s.rev_sample_y(),
+            make_sample(3, &[7, 8, 9, 4, 5, 6, 1, 2, 3])
+        );
+        assert_eq!(s.rotate_90(), make_sample(3, &[7, 4, 1, 8, 5, 2, 9, 6, 3]));
+        assert_eq!(s.rotate_ccw(), make_sample(3, &[3, 6, 9, 2, 5, 8, 1, 4, 7]));
+        assert_eq!(s.rev_sample(), make_sample(3, &[9, 8, 7, 6, 5, 4, 3, 2, 1]));
+        assert_eq!(
+            s.transpose_sample(),
+            make_sample(3, &[1, 4, 7, 2, 5, 8, 3, 6, 9])
+        );
+        assert_eq!(
+            s.anti_transpose_sample(),
+            make_sample(3, &[9, 6, 3, 8, 5, 2, 7, 4, 1])
+        );
+        assert_eq!(s.rotate_90().rotate_ccw(), s);
+    }
+
+    #[test]
+    fn test_transforms_non_square() {
+        // a b c
+        // d e f
+        let s = make_sample(3, &[1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(s.rotate_90(), make_sample(2, &[4, 1, 5, 2, 6, 3]));
+        assert_eq!(s.rotate_ccw(), make_sample(2, &[3, 6, 2, 5, 1, 4]));
+        assert_eq!(s.transpose_sample(), make_sample(2, &[1, 4, 2, 5, 3, 6]));
+        assert_eq!(s.reflect_x(), make_sample(3, &[3, 2, 1, 6, 5, 4]));
+        assert_eq!(s.rev_sample_y(), make_sample(3, &[4, 5, 6, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_symmetries() {
+        let s = make_sample(3, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let all = s.symmetries(8);
+        assert_eq!(
+            all,
+            vec![
+                s.clone(),
+                s.reflect_x(),
+                s.rotate_ccw(),
+                s.anti_transpose_sample(),
+                s.rev_sample(),
+                s.rev_sample_y(),
+                s.rotate_90(),
+                s.transpose_sample(),
+            ]
+        );
+
+        // All eight are different for an asymmetric pattern
+        for (i, a) in all.iter().enumerate() {
+            assert!(all[i + 1..].iter().all(|b| a != b));
+        }
+
+        // Lower symmetry takes a prefix of the group
+        assert_eq!(s.symmetries(1), vec![s.clone()]);
+        assert_eq!(s.symmetries(2), all[..2].to_vec());
+        assert_eq!(s.symmetries(5), all[..5].to_vec());
+
+        // A line is only changed by half of the group
+        let line = make_sample(3, &[0, 1, 0, 0, 1, 0, 0, 1, 0]);
+        let mut unique = line.symmetries(8);
+        unique.sort_by_key(|sample| sample.region.data.clone());
+        unique.dedup();
+        assert_eq!(unique.len(), 2);
+
+        // Only the half turn and the mirrors keep a 3x2 pattern's shape
+        let wide = make_sample(3, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            wide.symmetries(8),
+            vec![
+                wide.clone(),
+                wide.reflect_x(),
+                wide.rev_sample(),
+                wide.rev_sample_y(),
+            ]
+        );
     }
 }
@@ -16,20 +16,21 @@ impl EntropyCoord {
 
 impl Ord for EntropyCoord {
     //
-    // Written this way to make clippy happy
+    // Reversed so that the BinaryHeap pops the lowest entropy first,
+    // ties are broken by position to keep the pop order reproducible
     //
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self < other, self == other) {
-            (true, _) => Ordering::Less,
-            (false, true) => Ordering::Equal,
-            (false, false) => Ordering::Greater,
-        }
+        other
+            .entropy
+            .total_cmp(&self.entropy)
+            .then_with(|| other.coord.y.cmp(&self.coord.y))
+            .then_with(|| other.coord.x.cmp(&self.coord.x))
     }
 }
 
 impl PartialOrd for EntropyCoord {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.entropy.partial_cmp(&self.entropy)
+        Some(self.cmp(other))
     }
 }
 
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::data::colour::Rgba;
+use crate::data::grid2d::Grid2D;
+use crate::data::vector2::Vector2;
+
+//
+// Every way generation can fail
+//
+#[derive(Debug)]
+pub enum Error {
+    // Reading or writing a file failed
+    Io(std::io::Error),
+
+    // The input is not an image format we can decode (or encode)
+    UnsupportedFormat(String),
+
+    // The output file is already there and may not be replaced
+    OutputExists(String),
+
+    // Any other error reported by the image crate
+    Image(image::ImageError),
+
+    // The pattern doesn't fit inside the input image
+    InvalidPatternSize {
+        pattern_width: usize,
+        pattern_height: usize,
+        width: usize,
+        height: usize,
+    },
+
+    // Symmetry has to be between 1 and 8
+    InvalidSymmetry(usize),
+
+    // The output doesn't have a single cell
+    OutputTooSmall {
+        width: usize,
+        height: usize,
+        min_size: usize,
+    },
+
+    // The tileset description is malformed or inconsistent
+    Tileset(String),
+
+    // The palette to reduce the input to is empty or malformed
+    InvalidPalette(String),
+
+    // The compiled model file is malformed or inconsistent
+    InvalidModel(String),
+
+    // The inpainting guide doesn't fit the output
+    InvalidGuide(String),
+
+    // A position outside of a grid was accessed
+    OutOfBounds(Vector2),
+
+    // A cell of the output was never collapsed
+    Uncollapsed(Vector2),
+
+    // A validated output breaks the model in this many places
+    Violations(usize),
+
+    // Every attempt ended in a contradiction, the partial render
+    // shows what the latest one had left of every cell
+    RetriesExhausted {
+        attempts: usize,
+        partial: Box<Grid2D<Rgba>>,
+    },
+
+    // The constraints placed on the output contradict each other, the
+    // partial render shows the cells they left without any pattern
+    Unsatisfiable {
+        reason: String,
+        partial: Box<Grid2D<Rgba>>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    //
+    // What was left of the output when solving gave up, if anything
+    //
+    pub fn partial(&self) -> Option<&Grid2D<Rgba>> {
+        match self {
+            Error::RetriesExhausted { partial, .. } | Error::Unsatisfiable { partial, .. } => {
+                Some(partial)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "i/o error: {}", err),
+            Error::UnsupportedFormat(msg) => write!(f, "unsupported image format: {}", msg),
+            Error::OutputExists(path) => write!(f, "{} already exists", path),
+            Error::Image(err) => write!(f, "image error: {}", err),
+            Error::InvalidPatternSize {
+                pattern_width,
+                pattern_height,
+                width,
+                height,
+            } => write!(
+                f,
+                "pattern size {}x{} is invalid for a {}x{} input",
+                pattern_width, pattern_height, width, height
+            ),
+            Error::InvalidSymmetry(symmetry) => {
+                write!(f, "symmetry {} is not between 1 and 8", symmetry)
+            }
+            Error::OutputTooSmall {
+                width,
+                height,
+                min_size,
+            } => write!(
+                f,
+                "output {}x{} is too small, both sides must be at least {}",
+                width, height, min_size
+            ),
+            Error::Tileset(msg) => write!(f, "invalid tileset: {}", msg),
+            Error::InvalidPalette(msg) => write!(f, "invalid palette: {}", msg),
+            Error::InvalidModel(msg) => write!(f, "invalid model file: {}", msg),
+            Error::InvalidGuide(msg) => write!(f, "invalid guide: {}", msg),
+            Error::OutOfBounds(pos) => {
+                write!(f, "position ({}, {}) is out of bounds", pos.x, pos.y)
+            }
+            Error::Uncollapsed(pos) => {
+                write!(f, "cell ({}, {}) was never collapsed", pos.x, pos.y)
+            }
+            Error::Violations(count) => {
+                write!(f, "output breaks the model in {} places", count)
+            }
+            Error::RetriesExhausted { attempts, .. } => write!(
+                f,
+                "could not resolve contradictions after {} attempts",
+                attempts
+            ),
+            Error::Unsatisfiable { reason, .. } => {
+                write!(f, "constraints cannot be satisfied: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Error {
+        match err {
+            image::ImageError::IoError(err) => Error::Io(err),
+            image::ImageError::Unsupported(err) => Error::UnsupportedFormat(err.to_string()),
+            err => Error::Image(err),
+        }
+    }
+}
@@ -0,0 +1,656 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::core::{CoreState, Edge, Propagation, SolveOptions, Strategy, TileIndex};
+use crate::data::grid2d::Grid2D;
+use crate::error::Result;
+use crate::guide::Guide;
+use crate::image_reader::Image;
+use crate::model::{Model, ModelOptions};
+use crate::observer::{Event, Observer, Silent};
+use crate::output;
+use crate::palette::Palette;
+use crate::seed::Seed;
+use crate::tileset::Tileset;
+
+// What the model is built from
+#[derive(Debug, Clone)]
+enum Source {
+    // Example bitmap for the overlapping model
+    Image(Image),
+
+    // Tiles and neighbour rules for the simple tiled model
+    Tileset(Tileset),
+
+    // A model which is already compiled, e.g. loaded from a file
+    Model(Arc<Model>),
+}
+
+// How the colours of an input image are reduced
+#[derive(Debug, Clone)]
+enum Quantize {
+    // Pick this many colours with median cut
+    Colours(usize),
+
+    // Snap every pixel to the closest colour of the palette
+    Palette(Palette),
+}
+
+//
+// Builder for a single generation run
+//
+// let generation = Generator::from_dynamic_image(&img)
+//     .output_size(64, 64)
+//     .pattern_size(3)
+//     .seed(42)
+//     .generate()?;
+//
+#[derive(Debug, Clone)]
+pub struct Generator {
+    source: Source,
+    width: usize,
+    height: usize,
+    model_options: ModelOptions,
+    quantize: Option<Quantize>,
+    seed: Option<Seed>,
+    strategy: Strategy,
+    propagation: Propagation,
+    periodic_output: bool,
+    edges: [bool; 4],
+    guide: Option<Guide>,
+    blocks: (usize, usize),
+    snapshot_every: Option<usize>,
+    observer: Arc<dyn Observer>,
+}
+
+//
+// Result of a generation run
+//
+#[derive(Debug, Clone)]
+pub struct Generation {
+    // Sample ID chosen for every output cell
+    pub tiles: Grid2D<TileIndex>,
+
+    // The tiles rendered as pixels, one pixel per cell
+    // for the overlapping model, one tile per cell otherwise
+    pub image: RgbaImage,
+
+    // Snapshots of the collapse ending on `image`,
+    // only taken when `snapshot_every` is set
+    pub frames: Vec<RgbaImage>,
+
+    // Colours the input was reduced to, the output only uses these
+    pub palette: Option<Palette>,
+
+    pub stats: Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    // Seed the run used, pass it back in to reproduce the output
+    pub seed: Seed,
+
+    // Number of unique patterns in the model
+    pub pattern_count: usize,
+
+    // Number of times the output had to be split and solved
+    pub attempts: usize,
+
+    pub model_time: Duration,
+    pub solve_time: Duration,
+}
+
+impl Generator {
+    pub fn new(input: Image) -> Generator {
+        Generator::with_source(Source::Image(input))
+    }
+
+    pub fn from_dynamic_image(img: &DynamicImage) -> Generator {
+        Generator::new(Image::from_dynamic(img))
+    }
+
+    //
+    // Use the simple tiled model, the pattern size, symmetry
+    // and colour reduction are ignored
+    //
+    pub fn from_tileset(tileset: Tileset) -> Generator {
+        Generator::with_source(Source::Tileset(tileset))
+    }
+
+    //
+    // Reuse a compiled model, the model options are ignored
+    //
+    pub fn from_model(model: impl Into<Arc<Model>>) -> Generator {
+        Generator::with_source(Source::Model(model.into()))
+    }
+
+    fn with_source(source: Source) -> Generator {
+        Generator {
+            source,
+            width: 32,
+            height: 32,
+            model_options: ModelOptions::default(),
+            quantize: None,
+            seed: None,
+            strategy: Strategy::default(),
+            propagation: Propagation::default(),
+            periodic_output: false,
+            edges: [false; 4],
+            guide: None,
+            blocks: SolveOptions::default().blocks,
+            snapshot_every: None,
+            observer: Arc::new(Silent),
+        }
+    }
+
+    pub fn output_size(mut self, width: usize, height: usize) -> Generator {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn pattern_size(self, n: usize) -> Generator {
+        self.pattern_dimensions(n, n)
+    }
+
+    //
+    // Patterns which aren't square, e.g. wide ones for inputs with
+    // a strong horizontal structure
+    //
+    pub fn pattern_dimensions(mut self, width: usize, height: usize) -> Generator {
+        self.model_options.pattern_width = width;
+        self.model_options.pattern_height = height;
+        self
+    }
+
+    //
+    // Number of dihedral transforms (1..=8) applied to every pattern
+    //
+    pub fn symmetry(mut self, symmetry: usize) -> Generator {
+        self.model_options.symmetry = symmetry;
+        self
+    }
+
+    //
+    // Treat every fully transparent pixel of the input as the same
+    //
+    pub fn merge_transparent(mut self, merge: bool) -> Generator {
+        self.model_options.merge_transparent = merge;
+        self
+    }
+
+    //
+    // Reduce the input image to this many colours before it is sliced
+    // into patterns, the output only uses these colours
+    //
+    pub fn colours(mut self, count: usize) -> Generator {
+        self.quantize = Some(Quantize::Colours(count));
+        self
+    }
+
+    //
+    // Snap the input image to the colours of the palette instead
+    //
+    pub fn palette(mut self, palette: Palette) -> Generator {
+        self.quantize = Some(Quantize::Palette(palette));
+        self
+    }
+
+    //
+    // Let patterns wrap around the input edges (on by default)
+    //
+    pub fn periodic_input(mut self, periodic: bool) -> Generator {
+        self.model_options.periodic_input = periodic;
+        self
+    }
+
+    pub fn seed(mut self, seed: Seed) -> Generator {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Generator {
+        self.strategy = strategy;
+        self
+    }
+
+    //
+    // Spread big waves of propagation over the rayon threads (off by
+    // default), the output is the same either way
+    //
+    pub fn propagation(mut self, propagation: Propagation) -> Generator {
+        self.propagation = propagation;
+        self
+    }
+
+    //
+    // Keep a frame of the output after every `collapses` collapses,
+    // with undecided cells blending their possible patterns
+    //
+    pub fn snapshot_every(mut self, collapses: usize) -> Generator {
+        self.snapshot_every = Some(collapses);
+        self
+    }
+
+    //
+    // Report the progress of every run to the observer, e.g. a
+    // `ProgressBar` or a `JsonLog`, nothing is reported by default
+    //
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> Generator {
+        self.observer = observer;
+        self
+    }
+
+    //
+    // Wrap the output around its edges so that it tiles seamlessly
+    //
+    pub fn periodic_output(mut self, periodic: bool) -> Generator {
+        self.periodic_output = periodic;
+        self
+    }
+
+    //
+    // Only allow patterns found along the same edge of the input
+    // on this border of the output, `Edge::Bottom` pins the ground
+    //
+    pub fn constrain_edge(mut self, edge: Edge) -> Generator {
+        self.edges[edge.direction().to_idx()] = true;
+        self
+    }
+
+    //
+    // Split the output into a grid of blocks which are solved in
+    // parallel, more blocks keep more cores busy on big outputs
+    //
+    pub fn blocks(mut self, columns: usize, rows: usize) -> Generator {
+        self.blocks = (columns, rows);
+        self
+    }
+
+    //
+    // Keep the known pixels of the guide and only fill in the rest,
+    // the guide has to be the size of the rendered output
+    //
+    pub fn guide(mut self, guide: Guide) -> Generator {
+        self.guide = Some(guide);
+        self
+    }
+
+    //
+    // Build the model alone, without solving anything
+    //
+    pub fn build_model(&self) -> Result<Model> {
+        match &self.source {
+            Source::Image(input) => match &self.quantize {
+                None => Model::from_image(input, &self.model_options),
+                Some(quantize) => {
+                    let palette = match quantize {
+                        Quantize::Colours(count) => Palette::median_cut(input, *count)?,
+                        Quantize::Palette(palette) => palette.clone(),
+                    };
+                    let mut model = Model::from_image(&palette.apply(input), &self.model_options)?;
+                    model.palette = Some(palette);
+                    Ok(model)
+                }
+            },
+            Source::Tileset(tileset) => Model::from_tileset(tileset),
+            Source::Model(model) => Ok(Model::clone(model)),
+        }
+    }
+
+    //
+    // Model to solve with, a compiled one is shared rather than copied
+    //
+    fn shared_model(&self) -> Result<Arc<Model>> {
+        match &self.source {
+            Source::Model(model) => Ok(Arc::clone(model)),
+            _ => self.build_model().map(Arc::new),
+        }
+    }
+
+    //
+    // Build the model and solve the output
+    //
+    pub fn generate(&self) -> Result<Generation> {
+        let seed = self.seed.unwrap_or_else(rand::random);
+
+        let model_time = Instant::now();
+        let model = self.shared_model()?;
+        let model_time = model_time.elapsed();
+        self.observer.event(&Event::ModelBuilt {
+            patterns: model.size(),
+            elapsed: model_time,
+        });
+
+        let solve_time = Instant::now();
+        let options = SolveOptions {
+            strategy: self.strategy,
+            seed,
+            periodic: self.periodic_output,
+            edges: self.edges,
+            blocks: self.blocks,
+            propagation: self.propagation,
+            snapshot_every: self.snapshot_every,
+        };
+        let constraints = match &self.guide {
+            Some(guide) => model.guide_patterns(guide, self.width, self.height)?,
+            None => Vec::new(),
+        };
+        let solution = CoreState::par_solve_with(
+            &model,
+            self.width,
+            self.height,
+            &options,
+            &constraints,
+            Arc::clone(&self.observer),
+        )?;
+        let solve_time = solve_time.elapsed();
+
+        let image = output::to_image(&model.render(&solution.tiles));
+
+        Ok(Generation {
+            tiles: solution.tiles,
+            image,
+            frames: solution.frames.iter().map(output::to_image).collect(),
+            palette: model.palette.clone(),
+            stats: Stats {
+                seed,
+                pattern_count: model.size(),
+                attempts: solution.attempts,
+                model_time,
+                solve_time,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Generator;
+    use crate::error::Error;
+    use crate::guide::Guide;
+    use crate::tileset::Tileset;
+
+    #[test]
+    fn test_generate() {
+        let img = image::open("samples/Flowers.png").unwrap();
+        let generator = Generator::from_dynamic_image(&img)
+            .output_size(20, 16)
+            .pattern_size(3)
+            .seed(99);
+
+        let first = generator.generate().unwrap();
+        let second = generator.generate().unwrap();
+
+        assert_eq!(first.tiles, second.tiles);
+        assert_eq!(first.image, second.image);
+        assert_eq!((first.image.width(), first.image.height()), (20, 16));
+        assert_eq!(first.stats.seed, 99);
+        assert!(first.stats.pattern_count > 0);
+        assert!(first.stats.attempts >= 1);
+    }
+
+    #[test]
+    fn test_observer() {
+        use crate::observer::{Event, Observer};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Debug, Default)]
+        struct Events(Mutex<Vec<Event>>);
+
+        impl Observer for Events {
+            fn event(&self, event: &Event) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let events = Arc::new(Events::default());
+        let img = image::open("samples/Flowers.png").unwrap();
+        let generation = Generator::from_dynamic_image(&img)
+            .output_size(20, 16)
+            .pattern_size(3)
+            .seed(99)
+            .observer(events.clone())
+            .generate()
+            .unwrap();
+
+        let events = events.0.lock().unwrap();
+        assert!(matches!(events[0], Event::ModelBuilt { patterns, .. }
+            if patterns == generation.stats.pattern_count));
+        assert_eq!(
+            events[1],
+            Event::Started {
+                width: 20,
+                height: 16,
+                seed: 99
+            }
+        );
+        assert!(matches!(events.last(), Some(Event::Solved { attempts, .. })
+            if *attempts == generation.stats.attempts));
+
+        // The cells of the last attempt were collapsed, which
+        // is told every hundredth of the cells of the state
+        let last_split = events
+            .iter()
+            .rposition(|event| matches!(event, Event::Split { .. }))
+            .unwrap();
+        let after_split = &events[last_split..];
+        let finished = after_split
+            .iter()
+            .filter(|event| matches!(event, Event::BlockFinished { .. }))
+            .count();
+        assert_eq!(finished, 4);
+        let strips: Vec<usize> = events[..last_split]
+            .iter()
+            .rev()
+            .map_while(|event| match event {
+                Event::Collapsed {
+                    block: None,
+                    remaining,
+                } => Some(*remaining),
+                _ => None,
+            })
+            .collect();
+        let blocks = after_split
+            .iter()
+            .filter(|event| matches!(event, Event::Collapsed { block: Some(_), .. }))
+            .count();
+        assert!(!strips.is_empty() && blocks > 0);
+        assert!(strips
+            .iter()
+            .all(|remaining| remaining.is_multiple_of(20 * 16 / 100)));
+    }
+
+    #[test]
+    fn test_generate_from_model() {
+        let img = image::open("samples/Flowers.png").unwrap();
+        let generator = Generator::from_dynamic_image(&img)
+            .output_size(20, 16)
+            .pattern_size(3)
+            .seed(7);
+        let model = generator.build_model().unwrap();
+
+        let expected = generator.generate().unwrap();
+        let generation = Generator::from_model(model)
+            .output_size(20, 16)
+            .seed(7)
+            .generate()
+            .unwrap();
+        assert_eq!(generation.tiles, expected.tiles);
+        assert_eq!(generation.image, expected.image);
+    }
+
+    #[test]
+    fn test_generate_rectangular() {
+        use crate::image_reader::Image;
+        use crate::validate::{validate, validate_image};
+
+        let img = image::open("samples/Flowers.png").unwrap();
+        let generator = Generator::from_dynamic_image(&img)
+            .output_size(24, 24)
+            .pattern_dimensions(3, 2)
+            .seed(5);
+        let model = generator.build_model().unwrap();
+        assert_eq!((model.pattern_width, model.pattern_height), (3, 2));
+
+        let generation = generator.generate().unwrap();
+        assert_eq!(
+            (generation.image.width(), generation.image.height()),
+            (24, 24)
+        );
+        assert!(validate(&model, &generation.tiles, false).is_empty());
+
+        let output = Image::from_dynamic(&generation.image.into());
+        assert!(validate_image(&model, &output, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_generate_unsolvable() {
+        // A checkerboard can't wrap around an odd number of cells
+        let img = image::RgbaImage::from_fn(4, 4, |x, y| match (x + y) % 2 {
+            0 => image::Rgba([0, 0, 0, 255]),
+            _ => image::Rgba([255; 4]),
+        });
+
+        let result = Generator::from_dynamic_image(&image::DynamicImage::ImageRgba8(img))
+            .output_size(5, 4)
+            .pattern_size(2)
+            .periodic_output(true)
+            .seed(2)
+            .generate();
+
+        // The partial render still shows what was decided
+        match result {
+            Err(Error::RetriesExhausted { partial, .. }) => {
+                assert_eq!((partial.width, partial.height), (5, 4));
+                assert!(partial.data.contains(&crate::model::CONTRADICTION));
+            }
+            other => panic!("expected a failure, got {:?}", other.map(|g| g.stats)),
+        }
+    }
+
+    #[test]
+    fn test_generate_transparent() {
+        // Transparent holes in an opaque sprite
+        let mut img = image::open("samples/Flowers.png").unwrap().to_rgba8();
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if (x + y) % 5 == 0 {
+                pixel.0 = [x as u8, y as u8, 0, 0];
+            }
+        }
+
+        let generation = Generator::from_dynamic_image(&image::DynamicImage::ImageRgba8(img))
+            .output_size(16, 16)
+            .pattern_size(3)
+            .merge_transparent(true)
+            .seed(4)
+            .generate()
+            .unwrap();
+
+        let alphas: Vec<u8> = generation.image.pixels().map(|pixel| pixel.0[3]).collect();
+        assert!(alphas.contains(&0) && alphas.contains(&255));
+        assert!(generation
+            .image
+            .pixels()
+            .all(|pixel| pixel.0[3] == 255 || pixel.0 == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_generate_quantized() {
+        // Flowers with a bit of noise on every pixel
+        let mut img = image::open("samples/Flowers.png").unwrap().to_rgba8();
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let noise = ((x * 7 + y * 13) % 5) as u8;
+            for channel in &mut pixel.0[..3] {
+                *channel = channel.saturating_sub(noise);
+            }
+        }
+        let img = image::DynamicImage::ImageRgba8(img);
+        let generator = Generator::from_dynamic_image(&img).pattern_size(3);
+        let full = generator.build_model().unwrap();
+
+        let generation = generator
+            .colours(6)
+            .output_size(16, 16)
+            .seed(2)
+            .generate()
+            .unwrap();
+
+        // Fewer colours make for fewer patterns, all on the palette
+        let palette = generation.palette.unwrap();
+        assert!(palette.colours.len() <= 6);
+        assert!(generation.stats.pattern_count < full.size());
+        assert!(generation
+            .image
+            .pixels()
+            .all(|pixel| palette.colours.contains(&pixel.0)));
+    }
+
+    #[test]
+    fn test_generate_tiled() {
+        let tileset = Tileset::load("samples/Pipes/tileset.json").unwrap();
+        let generation = Generator::from_tileset(tileset)
+            .output_size(12, 10)
+            .seed(3)
+            .generate()
+            .unwrap();
+
+        // Each cell is drawn as a 3x3 tile
+        let image = &generation.image;
+        assert_eq!((image.width(), image.height()), (36, 30));
+
+        // Pipes have to continue into the neighbouring tile
+        let lit = |x: u32, y: u32| image.get_pixel(x, y).0[0] > 100;
+        for ty in 0..10 {
+            for tx in 0..12 {
+                let (x, y) = (tx * 3, ty * 3);
+                if tx + 1 < 12 {
+                    assert_eq!(lit(x + 2, y + 1), lit(x + 3, y + 1));
+                }
+                if ty + 1 < 10 {
+                    assert_eq!(lit(x + 1, y + 2), lit(x + 1, y + 3));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_guided() {
+        let img = image::open("samples/Flowers.png").unwrap();
+        let generator = Generator::from_dynamic_image(&img)
+            .output_size(24, 20)
+            .pattern_size(3);
+        let original = generator.clone().seed(1).generate().unwrap().image;
+
+        // Keep the left half of the output and fill in the right one
+        let mut painted = original.clone();
+        for (x, _, pixel) in painted.enumerate_pixels_mut() {
+            if x >= 12 {
+                pixel.0[3] = 0;
+            }
+        }
+        let guide = Guide::from_dynamic(&image::DynamicImage::ImageRgba8(painted));
+        assert_eq!(guide.known(), 12 * 20);
+
+        let filled = generator
+            .clone()
+            .guide(guide)
+            .seed(2)
+            .generate()
+            .unwrap()
+            .image;
+        for (x, y, pixel) in original.enumerate_pixels() {
+            if x < 12 {
+                assert_eq!(filled.get_pixel(x, y), pixel);
+            }
+        }
+
+        let wrong_size = Guide::from_dynamic(&image::DynamicImage::new_rgb8(10, 10));
+        assert!(matches!(
+            generator.guide(wrong_size).generate(),
+            Err(Error::InvalidGuide(_))
+        ));
+    }
+}
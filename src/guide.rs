@@ -0,0 +1,136 @@
+use image::{DynamicImage, GenericImageView};
+
+use crate::data::colour::{make_rgba, Rgba};
+use crate::data::grid2d::Grid2D;
+use crate::error::{Error, Result};
+
+//
+// Partially painted output used for inpainting, every known pixel
+// limits the patterns allowed on the cell which renders it and the
+// solver only makes up the unknown ones.
+//
+// The guide has the size of the rendered output, one pixel per cell
+// for the overlapping model and one tile per cell for the tiled model.
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guide {
+    // `None` marks a pixel which is left to the solver
+    pub pixels: Grid2D<Option<Rgba>>,
+}
+
+impl Guide {
+    //
+    // Fully transparent pixels are unknown, everything else is kept,
+    // an image without an alpha channel is known everywhere
+    //
+    pub fn from_dynamic(img: &DynamicImage) -> Guide {
+        let mut guide = Guide::known_everywhere(img);
+        for pixel in guide.pixels.data.iter_mut() {
+            if pixel.is_some_and(|rgba| rgba[3] == 0) {
+                *pixel = None;
+            }
+        }
+
+        guide
+    }
+
+    fn known_everywhere(img: &DynamicImage) -> Guide {
+        let mut pixels = Grid2D::init(img.width() as usize, img.height() as usize, None);
+        img.pixels().for_each(|(x, y, rgba)| {
+            pixels.data[y as usize * pixels.width + x as usize] = Some(make_rgba(&rgba));
+        });
+
+        Guide { pixels }
+    }
+
+    //
+    // Pixels which are not black in the mask are unknown and get
+    // filled in, the rest are taken from the image, even the
+    // transparent ones
+    //
+    pub fn with_mask(img: &DynamicImage, mask: &DynamicImage) -> Result<Guide> {
+        if img.dimensions() != mask.dimensions() {
+            return Err(Error::InvalidGuide(format!(
+                "mask is {}x{} but the image is {}x{}",
+                mask.width(),
+                mask.height(),
+                img.width(),
+                img.height()
+            )));
+        }
+
+        let mut guide = Guide::known_everywhere(img);
+        mask.to_luma8()
+            .pixels()
+            .zip(guide.pixels.data.iter_mut())
+            .for_each(|(luma, pixel)| {
+                if luma.0[0] > 0 {
+                    *pixel = None;
+                }
+            });
+
+        Ok(guide)
+    }
+
+    //
+    // Number of pixels the solver has to keep
+    //
+    pub fn known(&self) -> usize {
+        self.pixels
+            .data
+            .iter()
+            .filter(|pixel| pixel.is_some())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::Guide;
+    use crate::error::Error;
+
+    #[test]
+    fn test_transparent_pixels_are_unknown() {
+        let mut img = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        img.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        let guide = Guide::from_dynamic(&DynamicImage::ImageRgba8(img));
+
+        assert_eq!(guide.known(), 5);
+        assert_eq!(guide.pixels.data[0], Some([10, 20, 30, 255]));
+        assert_eq!(guide.pixels.data[1], None);
+
+        let opaque = Guide::from_dynamic(&DynamicImage::ImageRgb8(RgbImage::new(3, 2)));
+        assert_eq!(opaque.known(), 6);
+    }
+
+    #[test]
+    fn test_mask() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([1, 2, 3])));
+        let mut mask = GrayImage::new(2, 2);
+        mask.put_pixel(0, 1, Luma([255]));
+
+        let guide = Guide::with_mask(&img, &DynamicImage::ImageLuma8(mask)).unwrap();
+        assert_eq!(
+            guide.pixels.data,
+            vec![
+                Some([1, 2, 3, 255]),
+                Some([1, 2, 3, 255]),
+                None,
+                Some([1, 2, 3, 255])
+            ]
+        );
+
+        // The mask alone decides, transparent pixels can be kept
+        let clear = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+        let guide = Guide::with_mask(&clear, &DynamicImage::ImageLuma8(GrayImage::new(2, 2)));
+        assert_eq!(guide.unwrap().known(), 4);
+
+        let small = DynamicImage::ImageLuma8(GrayImage::new(1, 2));
+        assert!(matches!(
+            Guide::with_mask(&img, &small),
+            Err(Error::InvalidGuide(_))
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 use image::{DynamicImage, GenericImageView};
 
 use crate::data::{
-    colour::{self, make_rgb, Rgb},
+    colour::{self, make_rgba, Rgba},
     sample::Sample,
     vector2::Vector2,
 };
@@ -13,7 +13,7 @@ use crate::data::{
 pub struct Image {
     pub width: usize,
     pub height: usize,
-    pub pixels: Vec<Rgb>,
+    pub pixels: Vec<Rgba>,
 }
 
 impl Image {
@@ -28,6 +28,15 @@ impl Image {
         }
     }
 
+    //
+    // Initialize an image buffer holding the given image
+    //
+    pub fn from_dynamic(img: &DynamicImage) -> Image {
+        let mut image = Image::new(img.width() as usize, img.height() as usize);
+        image.load(img);
+        image
+    }
+
     //
     // Retrieve index position from Vector2 position
     //
@@ -38,7 +47,7 @@ impl Image {
     //
     // Retrieve colour from Vector2 position
     //
-    pub fn at(&self, at: Vector2) -> Rgb {
+    pub fn at(&self, at: Vector2) -> Rgba {
         let idx = self.idx(at);
         self.pixels[idx]
     }
@@ -46,7 +55,7 @@ impl Image {
     //
     // Set colour at Vector2 position
     //
-    pub fn set_colour(&mut self, at: Vector2, colour: Rgb) {
+    pub fn set_colour(&mut self, at: Vector2, colour: Rgba) {
         let idx = self.idx(at);
         self.pixels[idx] = colour;
     }
@@ -55,17 +64,33 @@ impl Image {
     // Load and Save an image
     //
     pub fn load(&mut self, img: &DynamicImage) {
-        img.pixels().for_each(|(x, y, rgb)| {
+        img.pixels().for_each(|(x, y, rgba)| {
             self.set_colour(
                 Vector2 {
                     x: x as i32,
                     y: y as i32,
                 },
-                make_rgb(&rgb),
+                make_rgba(&rgba),
             );
         });
     }
 
+    //
+    // Copy of the image where every fully transparent pixel is the same
+    //
+    pub fn merge_transparent(&self) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .copied()
+                .map(colour::merge_transparent)
+                .collect(),
+        }
+    }
+
     //
     // Slice a sample from the loaded image
     //
@@ -92,18 +117,25 @@ impl Image {
     //
     // Sample the image
     //
-    // n: Pattern size
+    // width:    Pattern width
+    // height:   Pattern height
+    // periodic: Wrap patterns around the image edges, otherwise
+    //           only take the ones which lie fully inside it
     //
-    pub fn sample(&self, n: i32) -> Vec<Sample> {
-        let sampler = |xs: u32, ys: u32| self.get_region(&xs, &ys, &(n as u32), &(n as u32));
+    pub fn sample(&self, width: usize, height: usize, periodic: bool) -> Vec<Sample> {
+        let sampler =
+            |xs: u32, ys: u32| self.get_region(&xs, &ys, &(width as u32), &(height as u32));
+        let inside = |x: usize, y: usize| x + width <= self.width && y + height <= self.height;
         self.pixels
             .iter()
             .enumerate()
             .fold(Vec::<Sample>::new(), |mut samples, (idx, _)| {
                 let x = idx % self.width;
                 let y = idx / self.width;
-                let sample = sampler(x as u32, y as u32);
-                samples.push(sample);
+                if periodic || inside(x, y) {
+                    let sample = sampler(x as u32, y as u32);
+                    samples.push(sample);
+                }
                 samples
             })
     }
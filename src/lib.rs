@@ -0,0 +1,28 @@
+//
+// Parallel Wave Function Collapse
+//
+// The `Generator` builder is the entry point for embedding the
+// generator, the remaining modules expose the building blocks.
+//
+extern crate image;
+
+pub mod core;
+pub mod data;
+pub mod entropy_coord;
+pub mod error;
+pub mod generator;
+pub mod guide;
+pub mod image_reader;
+pub mod model;
+pub mod model_file;
+pub mod observer;
+pub mod output;
+pub mod palette;
+pub mod seed;
+pub mod tileset;
+pub mod validate;
+
+pub use crate::core::{Edge, Propagation, Solution, SolveOptions, Strategy};
+pub use crate::error::{Error, Result};
+pub use crate::generator::{Generation, Generator, Stats};
+pub use crate::model::ModelOptions;
@@ -1,38 +1,228 @@
-use crate::core::CoreState;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
 use clap::Parser;
-use cli::Args;
-extern crate image;
+use cli::{BuildArgs, Cli, Command, GenerateArgs, ModelArgs, ModelCommand, ValidateArgs};
+use wfc::guide::Guide;
+use wfc::image_reader::Image;
+use wfc::model::Model;
+use wfc::observer::{JsonLog, Observer, ProgressBar, Silent};
+use wfc::output;
+use wfc::palette::Palette;
+use wfc::tileset::Tileset;
+use wfc::{validate, Error, Generator, Result};
 
 mod cli;
-mod core;
-mod data;
-mod entropy_coord;
-mod image_reader;
-mod model;
 
-fn main() {
+fn main() -> ExitCode {
     // Parse CLI <ImgPath> <Shape> <OutputWidth> <OutputHeight>
-    let args: Args = Args::parse();
-
-    let ans = CoreState::par_process(
-        &args.img_path,
-        args.n_dimensions,
-        args.width,
-        args.height,
-        args.rotation,
-    );
-    let w = ans
+    let cli = Cli::parse();
+
+    let result = match (&cli.command, &cli.model, &cli.generate) {
+        (Some(Command::Validate(args)), _, _) => run_validate(args),
+        (Some(Command::Model(ModelCommand::Build(args))), _, _) => run_build(args),
+        (Some(Command::Generate(args)), _, _) => Model::load(&args.model)
+            .and_then(|model| run(Generator::from_model(model), &args.generate)),
+        (None, Some(model), Some(args)) => {
+            load_generator(model).and_then(|generator| run(generator, args))
+        }
+        _ => unreachable!("clap requires the arguments to generate"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+//
+// Generator for the input, before any of the output settings
+//
+fn load_generator(args: &ModelArgs) -> Result<Generator> {
+    let generator = if args.tileset {
+        Generator::from_tileset(Tileset::load(&args.img_path)?)
+    } else {
+        Generator::from_dynamic_image(&image::open(&args.img_path)?)
+    };
+
+    let generator = match (args.colours, &args.palette) {
+        (Some(count), _) => generator.colours(count as usize),
+        (None, Some(path)) => generator.palette(Palette::load(path)?),
+        (None, None) => generator,
+    };
+
+    Ok(generator
+        .pattern_dimensions(args.n_dimensions.0, args.n_dimensions.1)
+        .symmetry(args.symmetry as usize)
+        .periodic_input(args.periodic_input)
+        .merge_transparent(args.merge_transparent))
+}
+
+fn run(generator: Generator, args: &GenerateArgs) -> Result<()> {
+    let result = generate(generator, args);
+
+    // Pipelines reading the log see the failure in it as well
+    if let (Err(err), true) = (&result, args.json_log) {
+        println!(
+            "{}",
+            serde_json::json!({"event": "error", "message": err.to_string()})
+        );
+    }
+    result
+}
+
+//
+// Print a line about the run, pipelines get `json` instead
+//
+fn report(args: &GenerateArgs, text: &str, json: Option<serde_json::Value>) {
+    if args.json_log {
+        if let Some(json) = json {
+            println!("{}", json);
+        }
+    } else if !args.quiet {
+        println!("{}", text);
+    }
+}
+
+fn generate(generator: Generator, args: &GenerateArgs) -> Result<()> {
+    output::check(Path::new(&args.output), args.force)?;
+    if let Some(path) = &args.render_partial {
+        output::check(Path::new(path), args.force)?;
+    }
+    if let Some(path) = &args.frames {
+        output::check_frames(Path::new(path), args.force)?;
+    }
+
+    // Keep the seed around so that any run can be reproduced
+    let seed = args.seed.unwrap_or_else(rand::random);
+    report(args, &format!("Seed: {}", seed), None);
+
+    let observer: Arc<dyn Observer> = if args.json_log {
+        Arc::new(JsonLog::stdout())
+    } else if args.quiet {
+        Arc::new(Silent)
+    } else {
+        Arc::new(ProgressBar::new())
+    };
+
+    let generator = args
+        .edges
         .iter()
-        .flat_map(|arr| vec![arr[0], arr[1], arr[2]])
-        .collect::<Vec<_>>();
-
-    // Save the buffer as "image.png"
-    image::save_buffer(
-        "image.png",
-        &w,
-        args.width as u32,
-        args.height as u32,
-        image::ColorType::Rgb8,
+        .fold(generator, |generator, &edge| generator.constrain_edge(edge));
+
+    let generator = match (&args.guide, &args.mask) {
+        (Some(guide), Some(mask)) => {
+            generator.guide(Guide::with_mask(&image::open(guide)?, &image::open(mask)?)?)
+        }
+        (Some(guide), None) => generator.guide(Guide::from_dynamic(&image::open(guide)?)),
+        _ => generator,
+    };
+
+    let generator = match (&args.frames, args.snapshot_every) {
+        (Some(_), Some(every)) => generator.snapshot_every(every as usize),
+        (Some(_), None) => generator.snapshot_every((args.width * args.height / 100).max(1)),
+        (None, _) => generator,
+    };
+
+    let generation = generator
+        .output_size(args.width, args.height)
+        .strategy(args.strategy)
+        .propagation(args.propagation)
+        .periodic_output(args.periodic_output)
+        .blocks(args.blocks.0, args.blocks.1)
+        .seed(seed)
+        .observer(observer)
+        .generate();
+
+    let generation = match (generation, &args.render_partial) {
+        (Err(err), Some(path)) => {
+            if let Some(partial) = err.partial() {
+                output::save(&output::to_image(partial), Path::new(path), args.force)?;
+                report(
+                    args,
+                    &format!("Partial output saved to {}", path),
+                    Some(saved("partial", path)),
+                );
+            }
+            return Err(err);
+        }
+        (generation, _) => generation?,
+    };
+
+    report(
+        args,
+        &format!(
+            "Model Creation Elapsed Time: {:.2?}",
+            generation.stats.model_time
+        ),
+        None,
+    );
+    if let Some(palette) = &generation.palette {
+        report(
+            args,
+            &palette_line(palette),
+            Some(serde_json::json!({"event": "palette", "colours": palette.hex()})),
+        );
+    }
+
+    output::save(&generation.image, Path::new(&args.output), args.force)?;
+    report(
+        args,
+        &format!("Output saved to {}", args.output),
+        Some(saved("output", &args.output)),
+    );
+
+    if let Some(path) = &args.frames {
+        output::save_frames(&generation.frames, Path::new(path), args.force)?;
+        report(
+            args,
+            &format!("{} frames saved to {}", generation.frames.len(), path),
+            Some(saved("frames", path)),
+        );
+    }
+    Ok(())
+}
+
+fn saved(kind: &str, path: &str) -> serde_json::Value {
+    serde_json::json!({"event": "saved", "kind": kind, "path": path})
+}
+
+fn run_build(args: &BuildArgs) -> Result<()> {
+    let model = load_generator(&args.model)?.build_model()?;
+    model.save(&args.output)?;
+
+    if let Some(palette) = &model.palette {
+        println!("{}", palette_line(palette));
+    }
+    println!("Saved {} patterns to {}", model.size(), args.output);
+    Ok(())
+}
+
+fn palette_line(palette: &Palette) -> String {
+    format!(
+        "Palette ({} colours): {}",
+        palette.colours.len(),
+        palette.hex().join(" ")
     )
-    .unwrap()
+}
+
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let model = load_generator(&args.model)?.build_model()?;
+    let output = Image::from_dynamic(&image::open(&args.output)?);
+
+    let violations = validate::validate_image(&model, &output, args.periodic_output)?;
+    for violation in &violations {
+        println!("{}", violation);
+    }
+
+    if violations.is_empty() {
+        println!("Output is valid");
+        Ok(())
+    } else {
+        Err(Error::Violations(violations.len()))
+    }
 }
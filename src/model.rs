@@ -1,20 +1,107 @@
 use std::collections::HashMap;
 
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use bit_set::BitSet;
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 
 use crate::core::TileEnablerCount;
+use crate::data::colour::{self, Rgba};
 use crate::data::direction::{Direction, ALL_DIRECTIONS};
+use crate::data::grid2d::Grid2D;
 use crate::data::sample::{Sample, SampleID};
+use crate::data::vector2::Vector2;
+use crate::error::{Error, Result};
+use crate::guide::Guide;
+use crate::palette::Palette;
+use crate::tileset::Tileset;
 
 extern crate bit_set;
 extern crate image;
 use crate::image_reader;
 
+// Tileset weights are fractional, the frequencies
+// are integers so they are scaled up by this much
+const TILE_WEIGHT_SCALE: f32 = 100.0;
+
+// Cells of a partial render which no sample fits any more
+pub const CONTRADICTION: Rgba = [255, 0, 255, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    // NxN patterns sliced from an example image which overlap
+    // their neighbours, each cell renders as one pixel
+    Overlapping,
+
+    // Hand-made tiles with explicit neighbour
+    // rules, each cell renders as a whole tile
+    Tiled,
+}
+
+// Settings for building an overlapping model from an example image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelOptions {
+    // Size of the patterns, they don't have to be square
+    pub pattern_width: usize,
+    pub pattern_height: usize,
+
+    // Number of dihedral transforms (1..=8) applied to every pattern
+    pub symmetry: usize,
+
+    // Let patterns wrap around the input edges, turn this off for
+    // inputs whose edges mean something (e.g. sky on top)
+    pub periodic_input: bool,
+
+    // Treat every fully transparent pixel as the same colour, so that
+    // invisible colour differences don't make up new patterns
+    pub merge_transparent: bool,
+}
+
+impl ModelOptions {
+    //
+    // Options for NxN patterns
+    //
+    pub fn new(pattern_size: usize) -> ModelOptions {
+        ModelOptions::rectangular(pattern_size, pattern_size)
+    }
+
+    pub fn rectangular(pattern_width: usize, pattern_height: usize) -> ModelOptions {
+        ModelOptions {
+            pattern_width,
+            pattern_height,
+            symmetry: 1,
+            periodic_input: true,
+            merge_transparent: false,
+        }
+    }
+}
+
+impl Default for ModelOptions {
+    fn default() -> ModelOptions {
+        ModelOptions::new(3)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Model {
     pub samples: Vec<Sample>,
     pub freq_map: Vec<(SampleID, (u32, f32))>,
     pub adjacency_rule: Vec<[bit_set::BitSet; 4]>,
+
+    // Number of cells a single pattern spans along each axis
+    pub pattern_width: usize,
+    pub pattern_height: usize,
+    pub kind: ModelKind,
+
+    // Patterns which touch each edge of the input, indexed by the
+    // direction of the edge (`edge_patterns[Up]` is the top edge)
+    pub edge_patterns: [bit_set::BitSet; 4],
+
+    // Settings an overlapping model was built with, None for tiled models
+    pub options: Option<ModelOptions>,
+
+    // Colours the input was reduced to, if it was
+    pub palette: Option<Palette>,
 }
 
 impl Model {
@@ -22,36 +109,84 @@ impl Model {
         self.freq_map[sample_id].1
     }
 
-    pub fn create(img_path: &str, n_dimensions: usize, rotation: bool) -> Model {
-        // Parse CLI <ImgPath> <Shape>
-
+    pub fn create(img_path: &str, options: &ModelOptions) -> Result<Model> {
         // Load image from args passed in
-        let img: image::DynamicImage = image::open(img_path).expect("Failed to open image");
+        let img: image::DynamicImage = image::open(img_path)?;
 
         // Process image
-        let mut image = image_reader::Image::new(img.width() as usize, img.height() as usize);
-        image.load(&img);
+        let image = crate::image_reader::Image::from_dynamic(&img);
+
+        Model::from_image(&image, options)
+    }
+
+    //
+    // Build the model from an image which is already in memory
+    //
+    pub fn from_image(image: &image_reader::Image, options: &ModelOptions) -> Result<Model> {
+        let ModelOptions {
+            pattern_width,
+            pattern_height,
+            symmetry,
+            periodic_input,
+            merge_transparent,
+        } = *options;
+
+        if pattern_width == 0
+            || pattern_height == 0
+            || pattern_width > image.width
+            || pattern_height > image.height
+        {
+            return Err(Error::InvalidPatternSize {
+                pattern_width,
+                pattern_height,
+                width: image.width,
+                height: image.height,
+            });
+        }
+
+        if !(1..=8).contains(&symmetry) {
+            return Err(Error::InvalidSymmetry(symmetry));
+        }
+
+        let merged;
+        let image = if merge_transparent {
+            merged = image.merge_transparent();
+            &merged
+        } else {
+            image
+        };
 
         // Retrieve image samples (includes duplicates)
-        let mut unprocessed_samples = image.sample(n_dimensions as i32);
-        if rotation {
+        let mut unprocessed_samples = image.sample(pattern_width, pattern_height, periodic_input);
+        // Augment with rotated and reflected copies
+        if symmetry > 1 {
             unprocessed_samples = unprocessed_samples
                 .par_iter()
-                .flat_map(|sample| sample.rotate())
+                .flat_map(|sample| sample.symmetries(symmetry))
                 .collect();
         }
 
-        // Calculate the number of times each unique sample appears
-        let freq_map: HashMap<Sample, i32> = unprocessed_samples.iter().fold(
-            HashMap::<Sample, i32>::new(),
-            |mut freq_map, sample| {
-                *freq_map.entry(sample.clone()).or_insert(0) += 1;
-                freq_map
-            },
-        );
+        // Calculate the number of times each unique sample appears,
+        // keeping the order of first appearance so that sample IDs
+        // are the same on every run
+        let mut sample_ids = HashMap::<Sample, SampleID>::new();
+        let mut samples = Vec::<Sample>::new();
+        let mut freqs = Vec::<i32>::new();
+        unprocessed_samples.into_iter().for_each(|sample| {
+            let id = *sample_ids.entry(sample.clone()).or_insert_with(|| {
+                samples.push(sample);
+                freqs.push(0);
+                samples.len() - 1
+            });
+            freqs[id] += 1;
+        });
 
-        // Unzip the frequency map
-        let (samples, freqs): (Vec<_>, Vec<_>) = freq_map.into_iter().unzip();
+        let edge_patterns = Model::find_edge_patterns(
+            image,
+            (pattern_width, pattern_height),
+            periodic_input,
+            &sample_ids,
+        );
 
         // Assign each frequency to an ID
         // Note: The ID works w.r.t the sample vector
@@ -66,33 +201,208 @@ impl Model {
             })
             .collect();
 
-        // In the form [s1][direction][s2]
-        let sample_size = samples.len();
+        let adjacency_rules = Model::overlap_adjacency(&samples);
+
+        Ok(Model {
+            samples,
+            freq_map: freq_mapping,
+            adjacency_rule: adjacency_rules,
+            pattern_width,
+            pattern_height,
+            kind: ModelKind::Overlapping,
+            edge_patterns,
+            options: Some(*options),
+            palette: None,
+        })
+    }
+
+    //
+    // Adjacency rules in the form [s1][direction][s2].
+    //
+    // Rather than comparing every pair of patterns, the patterns are
+    // bucketed by the part of them a neighbour in each direction
+    // overlaps, the patterns compatible with s1 are then the bucket
+    // whose overlap matches the one of s1 on the opposite side.
+    //
+    fn overlap_adjacency(samples: &[Sample]) -> Vec<[bit_set::BitSet; 4]> {
+        let buckets: Vec<HashMap<Sample, Vec<SampleID>>> = ALL_DIRECTIONS
+            .par_iter()
+            .map(|&direction| {
+                let mut bucket = HashMap::<Sample, Vec<SampleID>>::new();
+                for (id, sample) in samples.iter().enumerate() {
+                    bucket
+                        .entry(sample.overlap(direction.opposite()))
+                        .or_default()
+                        .push(id);
+                }
+                bucket
+            })
+            .collect();
+
+        samples
+            .par_iter()
+            .map(|sample| {
+                let mut rules: [bit_set::BitSet; 4] = Default::default();
+                for (direction, bucket) in ALL_DIRECTIONS.iter().zip(&buckets) {
+                    if let Some(ids) = bucket.get(&sample.overlap(*direction)) {
+                        rules[direction.to_idx()].extend(ids.iter().copied());
+                    }
+                }
+                rules
+            })
+            .collect()
+    }
+
+    //
+    // Collect the IDs of the patterns whose top left pixel, the one
+    // `render` draws, lies on each edge of the input. These are the
+    // only ones allowed on the matching edge of the output when it
+    // is constrained. The patterns of the bottom and right edges wrap
+    // around the input, without periodic input there are none and the
+    // ones lying flush against these edges are taken instead
+    //
+    fn find_edge_patterns(
+        image: &image_reader::Image,
+        (width, height): (usize, usize),
+        periodic: bool,
+        sample_ids: &HashMap<Sample, SampleID>,
+    ) -> [bit_set::BitSet; 4] {
+        let (last_x, last_y) = if periodic {
+            (image.width - 1, image.height - 1)
+        } else {
+            (image.width - width, image.height - height)
+        };
+        let (width, height) = (width as u32, height as u32);
+        let id_at = |x: usize, y: usize| {
+            let sample = image.get_region(&(x as u32), &(y as u32), &width, &height);
+            sample_ids[&sample]
+        };
+
+        let mut edges: [bit_set::BitSet; 4] = Default::default();
+        for x in 0..=last_x {
+            edges[Direction::Up.to_idx()].insert(id_at(x, 0));
+            edges[Direction::Down.to_idx()].insert(id_at(x, last_y));
+        }
+        for y in 0..=last_y {
+            edges[Direction::Left.to_idx()].insert(id_at(0, y));
+            edges[Direction::Right.to_idx()].insert(id_at(last_x, y));
+        }
+        edges
+    }
+
+    //
+    // Build a simple tiled model, every variant of every tile becomes
+    // a sample and the neighbour rules are expanded over all symmetries.
+    //
+    pub fn from_tileset(tileset: &Tileset) -> Result<Model> {
+        let tile_size = match tileset.tiles.first() {
+            Some(tile) => tile.image.width,
+            None => return Err(Error::Tileset("no tiles".to_string())),
+        };
+
+        let mut samples = Vec::<Sample>::new();
+        let mut freq_map = Vec::<(SampleID, (u32, f32))>::new();
+
+        // action[id][k] is the sample ID of sample `id` after the k-th
+        // transform: k < 4 rotates k times, k >= 4 then mirrors it
+        let mut action = Vec::<[SampleID; 8]>::new();
+        let mut first_variant = Vec::<SampleID>::new();
+
+        for tile in &tileset.tiles {
+            if tile.image.width != tile_size || tile.image.height != tile_size {
+                return Err(Error::Tileset(format!(
+                    "tile \"{}\" is not {}x{}",
+                    tile.name, tile_size, tile_size
+                )));
+            }
+
+            if tile.weight.is_nan() || tile.weight <= 0.0 {
+                return Err(Error::Tileset(format!(
+                    "tile \"{}\" needs a positive weight",
+                    tile.name
+                )));
+            }
+
+            let first = samples.len();
+            let symmetry = tile.symmetry;
+            let weight = ((tile.weight * TILE_WEIGHT_SCALE).round() as u32).max(1);
+            first_variant.push(first);
+
+            for variant in 0..symmetry.cardinality() {
+                let sample = if variant == 0 {
+                    tile.image
+                        .get_region(&0, &0, &(tile_size as u32), &(tile_size as u32))
+                } else if variant < 4 {
+                    samples[first + variant - 1].rotate_ccw()
+                } else {
+                    samples[first + variant - 4].reflect_x()
+                };
+
+                let rotate = |v| symmetry.rotate(v);
+                let reflect = |v| symmetry.reflect(v);
+                let row = [
+                    variant,
+                    rotate(variant),
+                    rotate(rotate(variant)),
+                    rotate(rotate(rotate(variant))),
+                    reflect(variant),
+                    reflect(rotate(variant)),
+                    reflect(rotate(rotate(variant))),
+                    reflect(rotate(rotate(rotate(variant)))),
+                ];
+
+                freq_map.push((
+                    samples.len(),
+                    (weight, (weight as f32) * (weight as f32).log2()),
+                ));
+                action.push(row.map(|v| first + v));
+                samples.push(sample);
+            }
+        }
+
         let bitsets: [bit_set::BitSet; 4] = [
-            bit_set::BitSet::with_capacity(sample_size),
-            bit_set::BitSet::with_capacity(sample_size),
-            bit_set::BitSet::with_capacity(sample_size),
-            bit_set::BitSet::with_capacity(sample_size),
+            bit_set::BitSet::with_capacity(samples.len()),
+            bit_set::BitSet::with_capacity(samples.len()),
+            bit_set::BitSet::with_capacity(samples.len()),
+            bit_set::BitSet::with_capacity(samples.len()),
         ];
-
         let mut adjacency_rules: Vec<[bit_set::BitSet; 4]> = vec![bitsets; samples.len()];
 
-        // Create adjacency rules
-        for s1 in 0..samples.len() {
-            for s2 in 0..samples.len() {
-                for direction in &ALL_DIRECTIONS {
-                    if samples[s1].compatible(&samples[s2], *direction) {
-                        adjacency_rules[s1][direction.to_idx()].insert(s2);
-                    }
+        for rule in &tileset.neighbors {
+            let (left_tile, left_variant) = tileset.find(&rule.left)?;
+            let (right_tile, right_variant) = tileset.find(&rule.right)?;
+            let left = first_variant[left_tile] + left_variant;
+            let right = first_variant[right_tile] + right_variant;
+
+            // Transform the pair as a whole, the right hand
+            // neighbour moves to wherever Right is mapped to
+            for (k, transform) in action[left].iter().zip(action[right].iter()).enumerate() {
+                let mut direction = Direction::Right;
+                (0..k % 4).for_each(|_| direction = direction.rotate_ccw());
+                if k >= 4 {
+                    direction = direction.reflect_x();
                 }
+
+                let (a, b) = transform;
+                adjacency_rules[*a][direction.to_idx()].insert(*b);
+                adjacency_rules[*b][direction.opposite().to_idx()].insert(*a);
             }
         }
 
-        Model {
+        // There is no input to take the edges from, so any tile can go anywhere
+        let every_tile: bit_set::BitSet = (0..samples.len()).collect();
+
+        Ok(Model {
             samples,
-            freq_map: freq_mapping,
+            freq_map,
             adjacency_rule: adjacency_rules,
-        }
+            pattern_width: 1,
+            pattern_height: 1,
+            kind: ModelKind::Tiled,
+            edge_patterns: [0, 1, 2, 3].map(|_| every_tile.clone()),
+            options: None,
+            palette: None,
+        })
     }
 
     pub fn get_initial_tile_enabler_counts(&self) -> Vec<TileEnablerCount> {
@@ -117,6 +427,206 @@ impl Model {
         self.samples.len()
     }
 
+    //
+    // Side length of the block of pixels a single cell renders to
+    //
+    pub fn cell_size(&self) -> usize {
+        match self.kind {
+            ModelKind::Overlapping => 1,
+            ModelKind::Tiled => self.samples[0].region.width,
+        }
+    }
+
+    //
+    // Whether fully transparent pixels were all made the same
+    // when the patterns were sliced, tiled models keep them as is
+    //
+    pub fn merges_transparent(&self) -> bool {
+        self.options
+            .is_some_and(|options| options.merge_transparent)
+    }
+
+    //
+    // The colour a pixel has in the input the patterns were sliced
+    // from, once the palette and transparency settings are applied
+    //
+    fn input_colour(&self, colour: Rgba) -> Rgba {
+        let colour = match &self.palette {
+            Some(palette) => palette.nearest(colour),
+            None => colour,
+        };
+        if self.merges_transparent() {
+            colour::merge_transparent(colour)
+        } else {
+            colour
+        }
+    }
+
+    //
+    // Patterns allowed on every cell which the guide knows something
+    // about, a pattern is allowed when it renders the same colour as
+    // every known pixel of the cell
+    //
+    pub fn guide_patterns(
+        &self,
+        guide: &Guide,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<(Vector2, bit_set::BitSet)>> {
+        let scale = self.cell_size();
+        if guide.pixels.width != width * scale || guide.pixels.height != height * scale {
+            return Err(Error::InvalidGuide(format!(
+                "guide is {}x{} but the output renders to {}x{}",
+                guide.pixels.width,
+                guide.pixels.height,
+                width * scale,
+                height * scale
+            )));
+        }
+
+        Ok((0..width * height)
+            .into_par_iter()
+            .filter_map(|idx| {
+                let cell = Vector2 {
+                    x: (idx % width) as i32,
+                    y: (idx / width) as i32,
+                };
+
+                // Known pixels of the cell, relative to its top left corner
+                let known: Vec<(Vector2, Rgba)> = (0..scale * scale)
+                    .filter_map(|offset| {
+                        let offset = Vector2 {
+                            x: (offset % scale) as i32,
+                            y: (offset / scale) as i32,
+                        };
+                        let pixel = Vector2 {
+                            x: cell.x * scale as i32 + offset.x,
+                            y: cell.y * scale as i32 + offset.y,
+                        };
+                        guide
+                            .pixels
+                            .get(pixel)
+                            .copied()
+                            .flatten()
+                            .map(|c| (offset, self.input_colour(c)))
+                    })
+                    .collect();
+
+                if known.is_empty() {
+                    return None;
+                }
+
+                let allowed = (0..self.size())
+                    .filter(|&id| {
+                        known
+                            .iter()
+                            .all(|&(offset, colour)| self.samples[id].at(offset) == colour)
+                    })
+                    .collect();
+                Some((cell, allowed))
+            })
+            .collect())
+    }
+
+    //
+    // Turn a grid of sample IDs into pixels, overlapping models use the
+    // top left pixel of each sample, tiled models draw the whole tile
+    //
+    pub fn render(&self, tiles: &Grid2D<SampleID>) -> Grid2D<Rgba> {
+        match self.kind {
+            ModelKind::Overlapping => Grid2D {
+                width: tiles.width,
+                height: tiles.height,
+                data: tiles
+                    .data
+                    .iter()
+                    .map(|&sample_id| self.samples[sample_id].get_top_left_pixel())
+                    .collect(),
+            },
+            ModelKind::Tiled => {
+                let tile_size = self.samples[0].region.width;
+                let width = tiles.width * tile_size;
+                let height = tiles.height * tile_size;
+
+                Grid2D {
+                    width,
+                    height,
+                    data: (0..width * height)
+                        .map(|idx| {
+                            let (x, y) = (idx % width, idx / width);
+                            let sample_id =
+                                tiles.data[(y / tile_size) * tiles.width + x / tile_size];
+                            self.samples[sample_id].at(Vector2 {
+                                x: (x % tile_size) as i32,
+                                y: (y % tile_size) as i32,
+                            })
+                        })
+                        .collect(),
+                }
+            }
+        }
+    }
+
+    //
+    // Render cells which may still be any of several samples, each pixel
+    // is the average of what the samples would draw there, weighted by
+    // how often they appear in the input. Cells without any sample left
+    // are drawn in `CONTRADICTION`
+    //
+    pub fn render_superposition(&self, cells: &Grid2D<BitSet>) -> Grid2D<Rgba> {
+        let average = |possible: &BitSet, at: Vector2| {
+            let mut sum = [0u64; 4];
+            let mut total = 0u64;
+            for sample_id in possible {
+                let weight = self.get_relative_freq(sample_id).0 as u64;
+                let colour = self.samples[sample_id].at(at);
+                for channel in 0..4 {
+                    sum[channel] += colour[channel] as u64 * weight;
+                }
+                total += weight;
+            }
+            match total {
+                0 => CONTRADICTION,
+                total => sum.map(|s| ((s + total / 2) / total) as u8),
+            }
+        };
+
+        match self.kind {
+            ModelKind::Overlapping => Grid2D {
+                width: cells.width,
+                height: cells.height,
+                data: cells
+                    .data
+                    .par_iter()
+                    .map(|possible| average(possible, Vector2 { x: 0, y: 0 }))
+                    .collect(),
+            },
+            ModelKind::Tiled => {
+                let tile_size = self.samples[0].region.width;
+                let width = cells.width * tile_size;
+                let height = cells.height * tile_size;
+
+                Grid2D {
+                    width,
+                    height,
+                    data: (0..width * height)
+                        .into_par_iter()
+                        .map(|idx| {
+                            let (x, y) = (idx % width, idx / width);
+                            let possible =
+                                &cells.data[(y / tile_size) * cells.width + x / tile_size];
+                            let at = Vector2 {
+                                x: (x % tile_size) as i32,
+                                y: (y % tile_size) as i32,
+                            };
+                            average(possible, at)
+                        })
+                        .collect(),
+                }
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_possible_nbrs(&self, sample_idx: SampleID, dir: Direction) -> Option<Vec<SampleID>> {
         let nbrs = &self.adjacency_rule[sample_idx][dir.to_idx()];
@@ -131,11 +641,14 @@ impl Model {
 }
 
 #[cfg(test)]
+// The older tests index with `*&ids[0]` and `id as usize`
+#[allow(clippy::deref_addrof, clippy::unnecessary_cast)]
 mod tests {
-    use super::Model;
-    use crate::data::grid2d::Grid2D;
+    use super::{Model, ModelOptions};
+    use crate::data::colour::opaque;
 
     fn find_sample_idx(model: &Model, sample: Vec<[u8; 3]>) -> Option<usize> {
+        let sample: Vec<_> = sample.into_iter().map(opaque).collect();
         model
             .samples
             .clone()
@@ -147,7 +660,7 @@ mod tests {
     fn check_valid_model() {
         use super::*;
         use crate::data::direction::Direction;
-        let model = Model::create("samples/ProcessExampleLong.png", 3, false);
+        let model = Model::create("samples/ProcessExampleLong.png", &ModelOptions::new(3)).unwrap();
         assert!(model.size() == 16);
 
         let sample_1 = find_sample_idx(
@@ -184,6 +697,9 @@ mod tests {
                 [136, 136, 255],
                 [0, 0, 0]
             ]
+            .into_iter()
+            .map(opaque)
+            .collect::<Vec<_>>()
         );
 
         assert_eq!(
@@ -199,6 +715,9 @@ mod tests {
                 [136, 136, 255],
                 [136, 136, 255],
             ]
+            .into_iter()
+            .map(opaque)
+            .collect::<Vec<_>>()
         );
 
         // Should return origin
@@ -219,6 +738,9 @@ mod tests {
                 [136, 136, 255],
                 [136, 136, 255],
             ]
+            .into_iter()
+            .map(opaque)
+            .collect::<Vec<_>>()
         );
 
         // Find the right compatible tile
@@ -237,6 +759,9 @@ mod tests {
                 [136, 136, 255],
                 [136, 136, 255],
             ]
+            .into_iter()
+            .map(opaque)
+            .collect::<Vec<_>>()
         );
 
         // Should return origin
@@ -257,6 +782,9 @@ mod tests {
                 [136, 136, 255],
                 [136, 136, 255],
             ]
+            .into_iter()
+            .map(opaque)
+            .collect::<Vec<_>>()
         );
 
         let sample_1 = find_sample_idx(
@@ -313,7 +841,10 @@ mod tests {
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
-                ],
+                ]
+                .into_iter()
+                .map(opaque)
+                .collect(),
             }
         }));
 
@@ -337,8 +868,246 @@ mod tests {
                     [0, 0, 0],
                     [136, 136, 255],
                     [0, 0, 0],
-                ],
+                ]
+                .into_iter()
+                .map(opaque)
+                .collect(),
             }
         }));
     }
+
+    #[test]
+    fn check_invalid_model() {
+        use crate::error::Error;
+
+        assert!(matches!(
+            Model::create("samples/missing.png", &ModelOptions::new(3)),
+            Err(Error::Io(_))
+        ));
+        assert!(matches!(
+            Model::create("Cargo.toml", &ModelOptions::new(3)),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            Model::create("samples/ProcessExample.png", &ModelOptions::new(0)),
+            Err(Error::InvalidPatternSize { .. })
+        ));
+        assert!(matches!(
+            Model::create(
+                "samples/ProcessExample.png",
+                &ModelOptions {
+                    symmetry: 9,
+                    ..ModelOptions::new(3)
+                }
+            ),
+            Err(Error::InvalidSymmetry(9))
+        ));
+        assert!(matches!(
+            Model::create("samples/ProcessExample.png", &ModelOptions::new(1000)),
+            Err(Error::InvalidPatternSize {
+                pattern_width: 1000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn check_tiled_model() {
+        use super::ModelKind;
+        use crate::data::direction::Direction;
+        use crate::tileset::Tileset;
+
+        let tileset = Tileset::load("samples/Pipes/tileset.json").unwrap();
+        let model = Model::from_tileset(&tileset).unwrap();
+
+        // empty + cross + 2 lines + 4 corners + 4 junctions
+        assert_eq!(model.size(), 12);
+        assert_eq!(model.kind, ModelKind::Tiled);
+        assert_eq!((model.pattern_width, model.pattern_height), (1, 1));
+
+        // Lines continue along their own axis but never end in the side of another line
+        let (vertical, horizontal) = (2, 3);
+        let rule = |a: usize, d: Direction, b| model.adjacency_rule[a][d.to_idx()].contains(b);
+        assert!(rule(vertical, Direction::Down, vertical));
+        assert!(rule(horizontal, Direction::Left, horizontal));
+        assert!(!rule(vertical, Direction::Right, horizontal));
+        assert!(!rule(horizontal, Direction::Up, vertical));
+    }
+
+    #[test]
+    fn check_symmetric_model() {
+        let plain = Model::create("samples/rooms.png", &ModelOptions::new(3)).unwrap();
+        let reflected = Model::create(
+            "samples/rooms.png",
+            &ModelOptions {
+                symmetry: 2,
+                ..ModelOptions::new(3)
+            },
+        )
+        .unwrap();
+        let full = Model::create(
+            "samples/rooms.png",
+            &ModelOptions {
+                symmetry: 8,
+                ..ModelOptions::new(3)
+            },
+        )
+        .unwrap();
+
+        assert!(plain.size() <= reflected.size());
+        assert!(reflected.size() <= full.size());
+        assert!(full.size() <= plain.size() * 8);
+
+        // Every transform of every pattern is part of the full model
+        for sample in &full.samples {
+            for variant in sample.symmetries(8) {
+                assert!(full.samples.contains(&variant));
+            }
+        }
+
+        // Mirroring alone doesn't produce rotations
+        for sample in &reflected.samples {
+            assert!(reflected.samples.contains(&sample.reflect_x()));
+        }
+    }
+
+    #[test]
+    fn check_non_periodic_input() {
+        let periodic = Model::create("samples/ProcessExample.png", &ModelOptions::new(3)).unwrap();
+        let bounded = Model::create(
+            "samples/ProcessExample.png",
+            &ModelOptions {
+                periodic_input: false,
+                ..ModelOptions::new(3)
+            },
+        )
+        .unwrap();
+
+        // Patterns crossing the border are gone, the rest are still there
+        let img = image::open("samples/ProcessExample.png").unwrap();
+        let image = crate::image_reader::Image::from_dynamic(&img);
+        let inside = image.sample(3, 3, false);
+        assert_eq!(inside.len(), (image.width - 2) * (image.height - 2));
+        assert!(bounded.size() < periodic.size());
+        for sample in &bounded.samples {
+            assert!(inside.contains(sample));
+            assert!(periodic.samples.contains(sample));
+        }
+    }
+
+    #[test]
+    fn check_edge_patterns() {
+        use crate::data::direction::Direction;
+
+        let img = image::open("samples/Flowers.png").unwrap();
+        let image = crate::image_reader::Image::from_dynamic(&img);
+        let model = Model::from_image(&image, &ModelOptions::new(3)).unwrap();
+
+        // The bottom edge holds exactly the patterns starting on the last input row
+        let last_y = image.height as u32 - 1;
+        let expected: bit_set::BitSet = (0..image.width as u32)
+            .map(|x| {
+                let sample = image.get_region(&x, &last_y, &3, &3);
+                model.samples.iter().position(|s| *s == sample).unwrap()
+            })
+            .collect();
+        assert_eq!(model.edge_patterns[Direction::Down.to_idx()], expected);
+
+        for edge in &model.edge_patterns {
+            assert!(!edge.is_empty() && edge.len() < model.size());
+        }
+    }
+
+    #[test]
+    fn check_overlap_adjacency() {
+        use crate::data::direction::ALL_DIRECTIONS;
+
+        for (path, n, symmetry) in [
+            ("samples/ProcessExampleLong.png", 3, 1),
+            ("samples/Flowers.png", 3, 8),
+            ("samples/rooms.png", 2, 8),
+            ("samples/cat.png", 4, 2),
+        ] {
+            let mut options = ModelOptions::new(n);
+            options.symmetry = symmetry;
+            let model = Model::create(path, &options).unwrap();
+
+            // Every pair compared pixel by pixel
+            for (s1, rules) in model.adjacency_rule.iter().enumerate() {
+                for direction in ALL_DIRECTIONS {
+                    let expected: Vec<usize> = (0..model.size())
+                        .filter(|&s2| model.samples[s1].compatible(&model.samples[s2], direction))
+                        .collect();
+                    let found: Vec<usize> = rules[direction.to_idx()].iter().collect();
+                    assert_eq!(found, expected, "{} {} {:?}", path, s1, direction);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_merge_transparent() {
+        use crate::image_reader::Image;
+
+        // Checkerboard of opaque red and transparent pixels
+        // whose hidden colour is different everywhere
+        let mut image = Image::new(4, 4);
+        for (idx, pixel) in image.pixels.iter_mut().enumerate() {
+            let (x, y) = (idx % 4, idx / 4);
+            *pixel = if (x + y) % 2 == 0 {
+                [255, 0, 0, 255]
+            } else {
+                [idx as u8, 7, 9, 0]
+            };
+        }
+
+        let mut options = ModelOptions::new(2);
+        let distinct = Model::from_image(&image, &options).unwrap();
+        options.merge_transparent = true;
+        let merged = Model::from_image(&image, &options).unwrap();
+
+        // Only the two phases of the checkerboard are left
+        assert!(distinct.size() > 2);
+        assert_eq!(merged.size(), 2);
+        assert!(merged.merges_transparent() && !distinct.merges_transparent());
+        for sample in &merged.samples {
+            assert!(sample.region.data.contains(&[0, 0, 0, 0]));
+        }
+    }
+
+    #[test]
+    fn check_render_superposition() {
+        use crate::data::grid2d::Grid2D;
+        use crate::image_reader::Image;
+        use bit_set::BitSet;
+
+        // Three black columns for every white one
+        let mut image = Image::new(4, 1);
+        image.pixels = vec![[0, 0, 0, 255], [0, 0, 0, 255], [0, 0, 0, 255], [255; 4]];
+        let model = Model::from_image(&image, &ModelOptions::new(1)).unwrap();
+        let black = find_sample_idx(&model, vec![[0, 0, 0]]).unwrap();
+        let white = find_sample_idx(&model, vec![[255, 255, 255]]).unwrap();
+
+        let cells = Grid2D {
+            width: 4,
+            height: 1,
+            data: vec![
+                BitSet::from_iter([black]),
+                BitSet::from_iter([white]),
+                BitSet::from_iter([black, white]),
+                BitSet::new(),
+            ],
+        };
+        let pixels = model.render_superposition(&cells);
+
+        // Collapsed cells match the regular render, the rest are blended
+        let collapsed = Grid2D {
+            width: 2,
+            height: 1,
+            data: vec![black, white],
+        };
+        assert_eq!(pixels.data[..2], model.render(&collapsed).data[..]);
+        assert_eq!(pixels.data[2], [64, 64, 64, 255]);
+        assert_eq!(pixels.data[3], super::CONTRADICTION);
+    }
 }
@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::sample::{Sample, SampleID};
+use crate::error::{Error, Result};
+use crate::model::{Model, ModelKind, ModelOptions};
+use crate::palette::Palette;
+
+// Bumped whenever the layout below changes
+const FORMAT_VERSION: u32 = 4;
+
+//
+// On-disk form of a compiled model, the bit sets are
+// stored as the list of sample IDs they contain
+//
+#[derive(Debug, Serialize, Deserialize)]
+struct ModelFile {
+    version: u32,
+    kind: ModelKind,
+    pattern_width: usize,
+    pattern_height: usize,
+    options: Option<ModelOptions>,
+    palette: Option<Palette>,
+    samples: Vec<Sample>,
+    freq_map: Vec<(SampleID, (u32, f32))>,
+    adjacency_rule: Vec<[Vec<SampleID>; 4]>,
+    edge_patterns: [Vec<SampleID>; 4],
+}
+
+impl Model {
+    //
+    // Write the model as JSON so that it can be
+    // loaded again without the input it came from
+    //
+    pub fn save(&self, path: &str) -> Result<()> {
+        let ids = |set: &bit_set::BitSet| set.iter().collect::<Vec<_>>();
+        let file = ModelFile {
+            version: FORMAT_VERSION,
+            kind: self.kind,
+            pattern_width: self.pattern_width,
+            pattern_height: self.pattern_height,
+            options: self.options,
+            palette: self.palette.clone(),
+            samples: self.samples.clone(),
+            freq_map: self.freq_map.clone(),
+            adjacency_rule: self
+                .adjacency_rule
+                .iter()
+                .map(|rules| rules.each_ref().map(ids))
+                .collect(),
+            edge_patterns: self.edge_patterns.each_ref().map(ids),
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, &file)
+            .map_err(|err| Error::InvalidModel(format!("{}: {}", path, err)))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    //
+    // Read back a model written by `save`
+    //
+    pub fn load(path: &str) -> Result<Model> {
+        let reader = BufReader::new(File::open(path)?);
+        let file: ModelFile = serde_json::from_reader(reader)
+            .map_err(|err| Error::InvalidModel(format!("{}: {}", path, err)))?;
+
+        file.check()
+            .map_err(|msg| Error::InvalidModel(format!("{}: {}", path, msg)))?;
+
+        let ids = |ids: Vec<SampleID>| ids.into_iter().collect::<bit_set::BitSet>();
+        Ok(Model {
+            samples: file.samples,
+            freq_map: file.freq_map,
+            adjacency_rule: file
+                .adjacency_rule
+                .into_iter()
+                .map(|rules| rules.map(ids))
+                .collect(),
+            pattern_width: file.pattern_width,
+            pattern_height: file.pattern_height,
+            kind: file.kind,
+            edge_patterns: file.edge_patterns.map(ids),
+            options: file.options,
+            palette: file.palette,
+        })
+    }
+}
+
+impl ModelFile {
+    //
+    // Make sure the solver can't index out of bounds with this model
+    //
+    fn check(&self) -> std::result::Result<(), String> {
+        if self.version != FORMAT_VERSION {
+            return Err(format!(
+                "format version {} is not supported, expected {}",
+                self.version, FORMAT_VERSION
+            ));
+        }
+
+        let size = self.samples.len();
+        let region = match self.samples.first() {
+            Some(sample) => (sample.region.width, sample.region.height),
+            None => return Err("no patterns".to_string()),
+        };
+        let fits = |sample: &Sample| {
+            (sample.region.width, sample.region.height) == region
+                && sample.region.data.len() == region.0 * region.1
+        };
+        if !self.samples.iter().all(fits) {
+            return Err("patterns differ in size".to_string());
+        }
+
+        if self.freq_map.len() != size || self.adjacency_rule.len() != size {
+            return Err(format!(
+                "expected frequencies and adjacency rules for {} patterns",
+                size
+            ));
+        }
+
+        let out_of_range = self
+            .adjacency_rule
+            .iter()
+            .flatten()
+            .chain(&self.edge_patterns)
+            .flatten()
+            .chain(self.freq_map.iter().map(|(id, _)| id))
+            .any(|&id| id >= size);
+        if out_of_range {
+            return Err(format!("pattern ID out of range for {} patterns", size));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::model::{Model, ModelOptions};
+    use crate::tileset::Tileset;
+
+    fn round_trip(model: &Model, name: &str) -> Model {
+        let path = std::env::temp_dir().join(format!("wfc-{}-{}.json", name, std::process::id()));
+        let path = path.to_str().unwrap();
+        model.save(path).unwrap();
+        let loaded = Model::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        loaded
+    }
+
+    fn assert_same(a: &Model, b: &Model) {
+        assert_eq!(a.samples, b.samples);
+        assert_eq!(a.freq_map, b.freq_map);
+        assert_eq!(a.adjacency_rule, b.adjacency_rule);
+        assert_eq!(a.edge_patterns, b.edge_patterns);
+        assert_eq!(a.pattern_width, b.pattern_width);
+        assert_eq!(a.pattern_height, b.pattern_height);
+        assert_eq!(a.kind, b.kind);
+        assert_eq!(a.options, b.options);
+        assert_eq!(a.palette, b.palette);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut options = ModelOptions::new(3);
+        options.symmetry = 8;
+        let model = Model::create("samples/Flowers.png", &options).unwrap();
+        assert_same(&model, &round_trip(&model, "flowers"));
+
+        let tileset = Tileset::load("samples/Pipes/tileset.json").unwrap();
+        let model = Model::from_tileset(&tileset).unwrap();
+        assert_same(&model, &round_trip(&model, "pipes"));
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let path = std::env::temp_dir().join(format!("wfc-invalid-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        std::fs::write(path, "{\"version\": 1}").unwrap();
+        assert!(matches!(Model::load(path), Err(Error::InvalidModel(_))));
+
+        // A rule pointing past the last pattern
+        let model = Model::create("samples/ProcessExample.png", &ModelOptions::new(3)).unwrap();
+        model.save(path).unwrap();
+        let json = std::fs::read_to_string(path).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["adjacency_rule"][0][0] = serde_json::json!([model.size()]);
+        std::fs::write(path, value.to_string()).unwrap();
+        assert!(matches!(Model::load(path), Err(Error::InvalidModel(_))));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::seed::Seed;
+
+//
+// Something that happened while generating, blocks are numbered in the
+// order `CoreState::split` hands them out and `None` is the full output
+//
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    // The model is ready to solve with
+    ModelBuilt {
+        patterns: usize,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+
+    // Solving an output of this many cells starts
+    Started {
+        width: usize,
+        height: usize,
+        seed: Seed,
+    },
+
+    // The output is split up again after a contradiction
+    Retry {
+        attempt: usize,
+    },
+
+    // The strips are collapsed and the blocks are handed out
+    Split {
+        attempt: usize,
+        blocks: usize,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+
+    // Cells were collapsed and `remaining` cells of the state are left,
+    // sent about every hundredth of the cells of the state
+    Collapsed {
+        block: Option<usize>,
+        remaining: usize,
+    },
+
+    // A run gave up, it is retried or the whole output is split again
+    Contradiction {
+        block: Option<usize>,
+    },
+
+    // Every cell of the block is collapsed
+    BlockFinished {
+        block: Option<usize>,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+
+    // Every block succeeded
+    Solved {
+        attempts: usize,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+    },
+
+    // Solving failed for good, the error is returned next
+    Failed {
+        attempts: usize,
+    },
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+//
+// Hook for following a generation run, the blocks are solved in
+// parallel so events can arrive from several threads at once
+//
+pub trait Observer: fmt::Debug + Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+// Ignores every event
+#[derive(Debug, Default)]
+pub struct Silent;
+
+impl Observer for Silent {
+    fn event(&self, _event: &Event) {}
+}
+
+//
+// Share of the output which is collapsed. Strip cells are counted in
+// the full output, block cells by how far the block got, which only
+// grows while candidates of the same block race each other or undo
+// decisions. The first collapse of a block only tells how big it is,
+// so the count falls a little short until the output is solved
+//
+#[derive(Debug, Default)]
+pub struct Progress {
+    cells: usize,
+    strips_remaining: Option<usize>,
+    // Most and fewest remaining cells seen for every block
+    blocks: Vec<(usize, usize)>,
+    solved: bool,
+    percent: Option<usize>,
+}
+
+impl Progress {
+    //
+    // Take the event into account, returns the percentage
+    // of collapsed cells whenever it changes
+    //
+    pub fn update(&mut self, event: &Event) -> Option<usize> {
+        match *event {
+            Event::Started { width, height, .. } => {
+                *self = Progress {
+                    cells: width * height,
+                    ..Progress::default()
+                };
+            }
+            Event::Retry { .. } => {
+                self.strips_remaining = None;
+                self.blocks.clear();
+            }
+            Event::Collapsed {
+                block: None,
+                remaining,
+            } => self.strips_remaining = Some(remaining),
+            Event::Collapsed {
+                block: Some(block),
+                remaining,
+            } => {
+                if self.blocks.len() <= block {
+                    self.blocks.resize(block + 1, (0, usize::MAX));
+                }
+                let (most, fewest) = &mut self.blocks[block];
+                *most = remaining.max(*most);
+                *fewest = remaining.min(*fewest);
+            }
+            Event::Solved { .. } => self.solved = true,
+            _ => return None,
+        }
+
+        let percent = (self.collapsed() * 100)
+            .checked_div(self.cells)
+            .unwrap_or(0);
+        if self.percent == Some(percent) {
+            return None;
+        }
+        self.percent = Some(percent);
+        Some(percent)
+    }
+
+    pub fn collapsed(&self) -> usize {
+        if self.solved {
+            return self.cells;
+        }
+        let strips = self.cells - self.strips_remaining.unwrap_or(self.cells);
+        let blocks: usize = self.blocks.iter().map(|(most, fewest)| most - fewest).sum();
+        (strips + blocks).min(self.cells)
+    }
+
+    pub fn cells(&self) -> usize {
+        self.cells
+    }
+}
+
+//
+// Bar of the collapsed share of the output on stderr,
+// nothing is drawn when stderr isn't a terminal
+//
+#[derive(Debug)]
+pub struct ProgressBar {
+    progress: Mutex<(Progress, usize)>,
+    enabled: bool,
+}
+
+const BAR_WIDTH: usize = 30;
+
+impl ProgressBar {
+    pub fn new() -> ProgressBar {
+        ProgressBar {
+            progress: Mutex::new((Progress::default(), 1)),
+            enabled: std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl Default for ProgressBar {
+    fn default() -> ProgressBar {
+        ProgressBar::new()
+    }
+}
+
+impl Observer for ProgressBar {
+    fn event(&self, event: &Event) {
+        if !self.enabled {
+            return;
+        }
+
+        // Drawn under the lock, so the bar never goes back
+        let mut guard = self.progress.lock().unwrap();
+        let (progress, attempt) = &mut *guard;
+        if let Event::Retry { attempt: next } = *event {
+            *attempt = next;
+        }
+
+        let mut stderr = std::io::stderr().lock();
+        if let Some(percent) = progress.update(event) {
+            let filled = percent * BAR_WIDTH / 100;
+            let _ = write!(
+                stderr,
+                "\r[{}{}] {:>3}% {}/{} cells, attempt {}",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                percent,
+                progress.collapsed(),
+                progress.cells(),
+                attempt
+            );
+        }
+        if matches!(event, Event::Solved { .. } | Event::Failed { .. }) {
+            let _ = writeln!(stderr);
+        }
+    }
+}
+
+//
+// One JSON object per line for every event, collapses are summed
+// up into a progress event whenever the percentage changes, and a
+// last one of 100% comes before the output is solved:
+//
+// {"event":"progress","percent":42,"collapsed":1720,"cells":4096}
+//
+// Lines are written under the same lock the progress is kept
+// under, so the percentage never goes down in the log
+//
+pub struct JsonLog {
+    state: Mutex<(Progress, Box<dyn Write + Send>)>,
+}
+
+impl JsonLog {
+    pub fn new(writer: impl Write + Send + 'static) -> JsonLog {
+        JsonLog {
+            state: Mutex::new((Progress::default(), Box::new(writer))),
+        }
+    }
+
+    pub fn stdout() -> JsonLog {
+        JsonLog::new(std::io::stdout())
+    }
+}
+
+impl fmt::Debug for JsonLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonLog").finish_non_exhaustive()
+    }
+}
+
+impl Observer for JsonLog {
+    fn event(&self, event: &Event) {
+        let mut guard = self.state.lock().unwrap();
+        let (progress, writer) = &mut *guard;
+
+        // Starting over resets the percentage without a line of its own
+        let percent = progress.update(event);
+        let mut lines = Vec::new();
+        if let (Event::Collapsed { .. } | Event::Solved { .. }, Some(percent)) = (event, percent) {
+            lines.push(serde_json::json!({
+                "event": "progress",
+                "percent": percent,
+                "collapsed": progress.collapsed(),
+                "cells": progress.cells(),
+            }));
+        }
+        if !matches!(event, Event::Collapsed { .. }) {
+            match serde_json::to_value(event) {
+                Ok(value) => lines.push(value),
+                Err(_) => return,
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        // A log which can't be written shouldn't stop the run
+        for line in lines {
+            let _ = writeln!(writer, "{}", line);
+        }
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{Event, JsonLog, Observer, Progress};
+
+    #[test]
+    fn test_progress() {
+        let mut progress = Progress::default();
+        let started = Event::Started {
+            width: 10,
+            height: 10,
+            seed: 0,
+        };
+        assert_eq!(progress.update(&started), Some(0));
+
+        // 20 strip cells, then two blocks of 40
+        let strips = Event::Collapsed {
+            block: None,
+            remaining: 80,
+        };
+        assert_eq!(progress.update(&strips), Some(20));
+        assert_eq!(progress.update(&strips), None);
+
+        for remaining in (0..40).rev() {
+            for block in 0..2 {
+                progress.update(&Event::Collapsed {
+                    block: Some(block),
+                    remaining,
+                });
+            }
+        }
+        // The first collapse of a block only shows how many it had left
+        assert_eq!(progress.collapsed(), 98);
+
+        // An undone decision doesn't move the bar back
+        progress.update(&Event::Collapsed {
+            block: Some(0),
+            remaining: 5,
+        });
+        assert_eq!(progress.collapsed(), 98);
+
+        assert_eq!(progress.update(&Event::Retry { attempt: 2 }), Some(0));
+
+        // Nothing is left once the output is solved
+        let solved = Event::Solved {
+            attempts: 2,
+            elapsed: Duration::from_secs(1),
+        };
+        assert_eq!(progress.update(&solved), Some(100));
+        assert_eq!(progress.collapsed(), 100);
+    }
+
+    // Writer shared with the test, so the log can be read back
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_log() {
+        let buffer = Buffer::default();
+        let log = JsonLog::new(buffer.clone());
+
+        log.event(&Event::Started {
+            width: 2,
+            height: 2,
+            seed: 7,
+        });
+        for remaining in [4, 3, 3, 2] {
+            log.event(&Event::Collapsed {
+                block: None,
+                remaining,
+            });
+        }
+        log.event(&Event::Solved {
+            attempts: 1,
+            elapsed: Duration::from_millis(1500),
+        });
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            serde_json::json!({"event": "started", "width": 2, "height": 2, "seed": 7})
+        );
+        assert_eq!(lines[1]["event"], "progress");
+        assert_eq!(lines[1]["percent"], 25);
+        assert_eq!(lines[2]["collapsed"], 2);
+        assert_eq!(
+            lines[3],
+            serde_json::json!({"event": "progress", "percent": 100, "collapsed": 4, "cells": 4})
+        );
+        assert_eq!(
+            lines[4],
+            serde_json::json!({"event": "solved", "attempts": 1, "elapsed": 1.5})
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use image::buffer::ConvertBuffer;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{ColorType, Delay, Frame, ImageEncoder, ImageFormat, ImageResult, RgbImage, RgbaImage};
+
+use crate::data::colour::Rgba;
+use crate::data::grid2d::Grid2D;
+use crate::error::{Error, Result};
+
+// How long each frame of an animation is shown, the
+// finished output at the end stays up for longer
+const FRAME_DELAY_MS: u32 = 40;
+const LAST_FRAME_DELAY_MS: u32 = 2000;
+
+//
+// Format matching the extension of `path`, if it can be written
+//
+pub fn format(path: &Path) -> Result<ImageFormat> {
+    let format = ImageFormat::from_path(path)?;
+    if !format.can_write() {
+        return Err(Error::UnsupportedFormat(format!(
+            "{:?} images can't be written",
+            format
+        )));
+    }
+    Ok(format)
+}
+
+//
+// Formats whose encoder takes RGBA pixels as they are,
+// the others only get the colour channels
+//
+fn keeps_alpha(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png
+            | ImageFormat::Bmp
+            | ImageFormat::Tga
+            | ImageFormat::Tiff
+            | ImageFormat::Ico
+            | ImageFormat::Qoi
+            | ImageFormat::Gif
+    )
+}
+
+//
+// Kind of PNM file the extension stands for, the `image` crate
+// writes every one of them as a PAM file (P7) on its own
+//
+fn pnm_subtype(path: &Path) -> Option<PnmSubtype> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "ppm" => Some(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+        "pgm" => Some(PnmSubtype::Graymap(SampleEncoding::Binary)),
+        "pbm" => Some(PnmSubtype::Bitmap(SampleEncoding::Binary)),
+        "pam" => Some(PnmSubtype::ArbitraryMap),
+        _ => None,
+    }
+}
+
+//
+// Grey and black and white maps get the brightness of the pixels, a
+// bitmap pixel is white from half brightness up. Only PAM files keep
+// the alpha channel.
+//
+fn write_pnm(
+    image: &RgbaImage,
+    subtype: PnmSubtype,
+    transparent: bool,
+    writer: impl Write,
+) -> ImageResult<()> {
+    let (width, height) = image.dimensions();
+    let encoder = |writer| PnmEncoder::new(writer).with_subtype(subtype);
+    match subtype {
+        PnmSubtype::Bitmap(_) => write_pbm(image, writer),
+        PnmSubtype::Graymap(_) => {
+            let grey = image::imageops::grayscale(image);
+            encoder(writer).write_image(grey.as_raw(), width, height, ColorType::L8)
+        }
+        PnmSubtype::ArbitraryMap if transparent => {
+            encoder(writer).write_image(image.as_raw(), width, height, ColorType::Rgba8)
+        }
+        _ => {
+            let rgb: RgbImage = image.convert();
+            encoder(writer).write_image(rgb.as_raw(), width, height, ColorType::Rgb8)
+        }
+    }
+}
+
+//
+// Binary bitmap (P4), written by hand as the encoder of the `image`
+// crate turns down bitmaps without a single white pixel. Every row
+// is padded to whole bytes and a set bit is a black pixel.
+//
+fn write_pbm(image: &RgbaImage, mut writer: impl Write) -> ImageResult<()> {
+    let grey = image::imageops::grayscale(image);
+    write!(writer, "P4\n{} {}\n", grey.width(), grey.height())?;
+    for row in grey.rows() {
+        let pixels: Vec<_> = row.collect();
+        let bytes: Vec<u8> = pixels
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pixel)| pixel.0[0] < 128)
+                    .fold(0, |byte, (i, _)| byte | 0x80 >> i)
+            })
+            .collect();
+        writer.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+//
+// Image of the rendered pixels
+//
+pub fn to_image(pixels: &Grid2D<Rgba>) -> RgbaImage {
+    RgbaImage::from_fn(pixels.width as u32, pixels.height as u32, |x, y| {
+        image::Rgba(pixels.data[pixels.width * y as usize + x as usize])
+    })
+}
+
+//
+// Fail early, before spending time on an output which can't be saved
+//
+pub fn check(path: &Path, overwrite: bool) -> Result<()> {
+    format(path)?;
+    if !overwrite && path.exists() {
+        return Err(Error::OutputExists(path.display().to_string()));
+    }
+    Ok(())
+}
+
+//
+// Write the image in the format matching the extension of `path`,
+// an existing file is only replaced when `overwrite` is set.
+//
+// Opaque images are written without an alpha channel, so are
+// images in formats which can't hold one.
+//
+pub fn save(image: &RgbaImage, path: &Path, overwrite: bool) -> Result<()> {
+    let format = format(path)?;
+    let mut writer = BufWriter::new(create(path, overwrite)?);
+    let transparent = image.pixels().any(|pixel| pixel.0[3] < 255);
+    let written = match pnm_subtype(path) {
+        Some(subtype) if format == ImageFormat::Pnm => {
+            write_pnm(image, subtype, transparent, &mut writer)
+        }
+        _ if transparent && keeps_alpha(format) => image.write_to(&mut writer, format),
+        _ => ConvertBuffer::<RgbImage>::convert(image).write_to(&mut writer, format),
+    };
+    let written = written
+        .map_err(Error::from)
+        .and_then(|()| writer.flush().map_err(Error::from));
+
+    // Don't leave a truncated image behind
+    if written.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    written
+}
+
+//
+// Open the file for writing, an existing file is only
+// truncated when `overwrite` is set
+//
+fn create(path: &Path, overwrite: bool) -> Result<File> {
+    // create_new checks and creates in one step, so a file
+    // appearing in the meantime is never clobbered
+    let file = if overwrite {
+        File::create(path)
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(path)
+    };
+    file.map_err(|err| match err.kind() {
+        ErrorKind::AlreadyExists => Error::OutputExists(path.display().to_string()),
+        _ => Error::Io(err),
+    })
+}
+
+//
+// Animations are written as a GIF when the path ends in .gif,
+// otherwise as numbered PNGs in the directory at `path`
+//
+fn is_gif(path: &Path) -> bool {
+    matches!(ImageFormat::from_path(path), Ok(ImageFormat::Gif))
+}
+
+fn frame_path(dir: &Path, idx: usize) -> PathBuf {
+    dir.join(format!("frame_{:04}.png", idx))
+}
+
+//
+// Frames already in the directory at `dir`, by their number,
+// from whichever run wrote them
+//
+fn existing_frames(dir: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut frames = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let idx = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("frame_")?.strip_suffix(".png"))
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse().ok());
+        if let Some(idx) = idx {
+            frames.push((idx, path));
+        }
+    }
+    frames.sort();
+    Ok(frames)
+}
+
+//
+// Same as `check`, for the frames of an animation
+//
+pub fn check_frames(path: &Path, overwrite: bool) -> Result<()> {
+    if is_gif(path) {
+        return check(path, overwrite);
+    }
+
+    // A file in the way of the directory can't be replaced, even with overwrite
+    if path.is_file() {
+        return Err(Error::OutputExists(path.display().to_string()));
+    }
+    // Frames of another run are never mixed in with the new ones
+    if !overwrite {
+        if let Some((_, frame)) = existing_frames(path)?.first() {
+            return Err(Error::OutputExists(frame.display().to_string()));
+        }
+    }
+    Ok(())
+}
+
+//
+// Write the frames as an animation that loops forever, or as
+// frame_0000.png, frame_0001.png, ... in the directory at `path`,
+// where frames of a longer run before are removed when `overwrite`
+// is set
+//
+pub fn save_frames(frames: &[RgbaImage], path: &Path, overwrite: bool) -> Result<()> {
+    if !is_gif(path) {
+        std::fs::create_dir_all(path)?;
+        for (idx, frame) in frames.iter().enumerate() {
+            save(frame, &frame_path(path, idx), overwrite)?;
+        }
+        if overwrite {
+            for (_, stale) in existing_frames(path)?
+                .into_iter()
+                .filter(|&(idx, _)| idx >= frames.len())
+            {
+                std::fs::remove_file(stale)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut writer = BufWriter::new(create(path, overwrite)?);
+    let written = write_gif(frames, &mut writer).and_then(|()| writer.flush().map_err(Error::from));
+
+    if written.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    written
+}
+
+fn write_gif(frames: &[RgbaImage], writer: impl Write) -> Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    for (idx, frame) in frames.iter().enumerate() {
+        let delay = if idx + 1 == frames.len() {
+            LAST_FRAME_DELAY_MS
+        } else {
+            FRAME_DELAY_MS
+        };
+        let delay = Delay::from_numer_denom_ms(delay, 1);
+        encoder.encode_frame(Frame::from_parts(frame.clone(), 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use image::{ColorType, Rgba, RgbaImage};
+
+    use super::{check, check_frames, save, save_frames};
+    use crate::error::Error;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wfc-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        let image = RgbaImage::from_fn(5, 3, |x, y| Rgba([x as u8 * 40, y as u8 * 80, 7, 255]));
+        let mut clear = image.clone();
+        clear.put_pixel(1, 1, Rgba([0, 0, 0, 0]));
+
+        for name in ["out.png", "out.bmp", "out.tga", "out.ppm"] {
+            let path = temp_path(name);
+            save(&image, &path, false).unwrap();
+            let saved = image::open(&path).unwrap();
+            assert_eq!(saved.color(), ColorType::Rgb8);
+            assert_eq!(saved.to_rgba8(), image);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        // PNM files are written as the kind their extension stands for
+        let black = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 255]));
+        for (name, magic) in [
+            ("out.ppm", "P6"),
+            ("out.pgm", "P5"),
+            ("out.pbm", "P4"),
+            ("out.pam", "P7"),
+        ] {
+            for image in [&image, &black] {
+                let path = temp_path(name);
+                save(image, &path, false).unwrap();
+                assert!(std::fs::read(&path).unwrap().starts_with(magic.as_bytes()));
+                let saved = image::open(&path).unwrap().to_rgba8();
+                if image == &black {
+                    assert_eq!(&saved, image);
+                }
+                std::fs::remove_file(&path).unwrap();
+            }
+        }
+
+        // Transparency survives where the format has room for it
+        for name in ["clear.png", "clear.bmp", "clear.tga"] {
+            let path = temp_path(name);
+            save(&clear, &path, false).unwrap();
+            assert_eq!(image::open(&path).unwrap().to_rgba8(), clear);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        assert!(matches!(
+            save(&image, &temp_path("out.unknown"), false),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(!temp_path("out.unknown").exists());
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let path = temp_path("exists.png");
+        let image = RgbaImage::new(2, 2);
+        save(&image, &path, false).unwrap();
+
+        assert!(matches!(check(&path, false), Err(Error::OutputExists(_))));
+        assert!(check(&path, true).is_ok());
+        assert!(matches!(
+            save(&RgbaImage::new(4, 4), &path, false),
+            Err(Error::OutputExists(_))
+        ));
+        assert_eq!(image::open(&path).unwrap().width(), 2);
+
+        save(&RgbaImage::new(4, 4), &path, true).unwrap();
+        assert_eq!(image::open(&path).unwrap().width(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_frames() {
+        use image::codecs::gif::GifDecoder;
+        use image::AnimationDecoder;
+
+        let frames: Vec<RgbaImage> = (0..3)
+            .map(|idx| RgbaImage::from_pixel(4, 3, Rgba([idx * 100, 0, 0, 255])))
+            .collect();
+
+        let gif = temp_path("frames.gif");
+        save_frames(&frames, &gif, false).unwrap();
+        let decoder = GifDecoder::new(std::fs::File::open(&gif).unwrap()).unwrap();
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2].buffer(), &frames[2]);
+        assert!(matches!(
+            check_frames(&gif, false),
+            Err(Error::OutputExists(_))
+        ));
+        std::fs::remove_file(&gif).unwrap();
+
+        // Anything else is a directory of numbered PNGs
+        let dir = temp_path("frames");
+        save_frames(&frames, &dir, false).unwrap();
+        let frame = image::open(dir.join("frame_0002.png")).unwrap();
+        assert_eq!(frame.to_rgba8(), frames[2]);
+        assert!(!dir.join("frame_0003.png").exists());
+        assert!(matches!(
+            check_frames(&dir, false),
+            Err(Error::OutputExists(_))
+        ));
+        assert!(check_frames(&dir, true).is_ok());
+
+        // A shorter run leaves none of the frames of the one before
+        std::fs::write(dir.join("notes.txt"), "kept").unwrap();
+        save_frames(&frames[..1], &dir, true).unwrap();
+        assert!(dir.join("frame_0000.png").exists());
+        assert!(!dir.join("frame_0001.png").exists());
+        assert!(!dir.join("frame_0002.png").exists());
+        assert!(dir.join("notes.txt").exists());
+
+        // Any frame is in the way, not just the first
+        std::fs::remove_file(dir.join("frame_0000.png")).unwrap();
+        std::fs::write(dir.join("frame_0005.png"), "").unwrap();
+        assert!(matches!(
+            check_frames(&dir, false),
+            Err(Error::OutputExists(path)) if path.ends_with("frame_0005.png")
+        ));
+        std::fs::remove_file(dir.join("frame_0005.png")).unwrap();
+        assert!(check_frames(&dir, false).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
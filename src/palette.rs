@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::data::colour::Rgba;
+use crate::error::{Error, Result};
+use crate::image_reader::Image;
+
+//
+// Colours an input is reduced to before it is sliced into patterns,
+// nearly identical colours (anti-aliasing, noise, photographs) would
+// otherwise each make up patterns of their own
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Palette {
+    pub colours: Vec<Rgba>,
+}
+
+impl Palette {
+    pub fn new(colours: Vec<Rgba>) -> Result<Palette> {
+        if colours.is_empty() {
+            return Err(Error::InvalidPalette("no colours".to_string()));
+        }
+        Ok(Palette { colours })
+    }
+
+    //
+    // Pick up to `count` colours for the image with median cut: the box
+    // of colours with the widest channel is split at its median pixel
+    // along that channel until there are enough boxes, every box then
+    // gives its average colour
+    //
+    pub fn median_cut(image: &Image, count: usize) -> Result<Palette> {
+        if count == 0 {
+            return Err(Error::InvalidPalette(
+                "at least one colour is needed".to_string(),
+            ));
+        }
+
+        let mut pixels = HashMap::<Rgba, usize>::new();
+        for &colour in &image.pixels {
+            *pixels.entry(colour).or_default() += 1;
+        }
+        // Sorted so that the palette doesn't depend on the hash order
+        let mut pixels: Vec<(Rgba, usize)> = pixels.into_iter().collect();
+        pixels.sort_unstable();
+
+        let mut boxes = vec![pixels];
+        while boxes.len() < count {
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .map(|(idx, colours)| (widest_channel(colours), idx))
+                .filter(|((_, range), _)| *range > 0)
+                .max_by_key(|&((_, range), idx)| (range, std::cmp::Reverse(idx)));
+            let ((channel, _), idx) = match widest {
+                Some(widest) => widest,
+                // Every box holds a single colour
+                None => break,
+            };
+
+            let mut colours = boxes.remove(idx);
+            colours.sort_by_key(|(colour, _)| colour[channel]);
+
+            // Both halves keep at least one colour
+            let total: usize = colours.iter().map(|(_, n)| n).sum();
+            let mut seen = 0;
+            let split = colours
+                .iter()
+                .position(|(_, n)| {
+                    seen += n;
+                    seen * 2 >= total
+                })
+                .map_or(1, |idx| idx + 1)
+                .clamp(1, colours.len() - 1);
+
+            let upper = colours.split_off(split);
+            boxes.insert(idx, upper);
+            boxes.insert(idx, colours);
+        }
+
+        let mut colours: Vec<Rgba> = Vec::new();
+        for average in boxes.iter().filter_map(|colours| average(colours)) {
+            if !colours.contains(&average) {
+                colours.push(average);
+            }
+        }
+        Palette::new(colours)
+    }
+
+    //
+    // Read a palette file, either an image whose distinct pixels are the
+    // colours, or text with hex colours (#rrggbb or #rrggbbaa) separated
+    // by whitespace or commas, anything after // on a line is ignored
+    //
+    pub fn load(path: &str) -> Result<Palette> {
+        if image::ImageFormat::from_path(path).is_ok() {
+            let image = Image::from_dynamic(&image::open(path)?);
+            let mut colours: Vec<Rgba> = Vec::new();
+            for &colour in &image.pixels {
+                if !colours.contains(&colour) {
+                    colours.push(colour);
+                }
+            }
+            return Palette::new(colours);
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        Palette::parse(&text).map_err(|err| match err {
+            Error::InvalidPalette(msg) => Error::InvalidPalette(format!("{}: {}", path, msg)),
+            err => err,
+        })
+    }
+
+    //
+    // Colours of a text palette, see `load`
+    //
+    pub fn parse(text: &str) -> Result<Palette> {
+        let colours = text
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(parse_hex)
+            .collect::<Result<Vec<_>>>()?;
+        Palette::new(colours)
+    }
+
+    //
+    // Closest colour of the palette, every channel weighs the same
+    //
+    pub fn nearest(&self, colour: Rgba) -> Rgba {
+        let distance = |other: &Rgba| {
+            colour
+                .iter()
+                .zip(other)
+                .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        *self.colours.iter().min_by_key(|c| distance(c)).unwrap()
+    }
+
+    //
+    // Copy of the image with every pixel snapped to the palette
+    //
+    pub fn apply(&self, image: &Image) -> Image {
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels: image
+                .pixels
+                .par_iter()
+                .map(|&colour| self.nearest(colour))
+                .collect(),
+        }
+    }
+
+    //
+    // The colours as hex codes, alpha is only spelled out when it isn't opaque
+    //
+    pub fn hex(&self) -> Vec<String> {
+        self.colours
+            .iter()
+            .map(|&[r, g, b, a]| match a {
+                255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+                a => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            })
+            .collect()
+    }
+}
+
+//
+// Channel with the largest spread of values and the size of the spread
+//
+fn widest_channel(colours: &[(Rgba, usize)]) -> (usize, u8) {
+    (0..4)
+        .map(|channel| {
+            let values = colours.iter().map(|(colour, _)| colour[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(channel, range)| (range, std::cmp::Reverse(channel)))
+        .unwrap()
+}
+
+//
+// Average colour of the pixels, weighted by how often each appears
+//
+fn average(colours: &[(Rgba, usize)]) -> Option<Rgba> {
+    let total: usize = colours.iter().map(|(_, n)| n).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut sum = [0usize; 4];
+    for (colour, n) in colours {
+        for channel in 0..4 {
+            sum[channel] += colour[channel] as usize * n;
+        }
+    }
+    Some(sum.map(|s| ((s + total / 2) / total) as u8))
+}
+
+fn parse_hex(token: &str) -> Result<Rgba> {
+    let digits = token.strip_prefix('#').unwrap_or(token);
+    let invalid = || Error::InvalidPalette(format!("\"{}\" is not a hex colour", token));
+    if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut colour = [255; 4];
+    for (channel, value) in colour.iter_mut().enumerate().take(digits.len() / 2) {
+        *value =
+            u8::from_str_radix(&digits[channel * 2..channel * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palette;
+    use crate::error::Error;
+    use crate::image_reader::Image;
+
+    #[test]
+    fn test_median_cut() {
+        // Two clusters of near identical reds and blues
+        let mut image = Image::new(8, 2);
+        for (idx, pixel) in image.pixels.iter_mut().enumerate() {
+            let noise = (idx % 4) as u8;
+            *pixel = if idx < 8 {
+                [200 + noise, 10, 10, 255]
+            } else {
+                [10, 10, 200 + noise, 255]
+            };
+        }
+
+        let palette = Palette::median_cut(&image, 2).unwrap();
+        assert_eq!(palette.colours.len(), 2);
+        assert!(palette.colours.contains(&[202, 10, 10, 255]));
+        assert!(palette.colours.contains(&[10, 10, 202, 255]));
+
+        let reduced = palette.apply(&image);
+        assert!(reduced
+            .pixels
+            .iter()
+            .all(|colour| palette.colours.contains(colour)));
+        assert_eq!(reduced.pixels[0], [202, 10, 10, 255]);
+
+        // Never more colours than the image has
+        assert_eq!(Palette::median_cut(&image, 100).unwrap().colours.len(), 8);
+        assert!(matches!(
+            Palette::median_cut(&image, 0),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse() {
+        let palette = Palette::parse("#000000, #ff8000 // orange\n\n  00ff0080\n").unwrap();
+        assert_eq!(
+            palette.colours,
+            vec![[0, 0, 0, 255], [255, 128, 0, 255], [0, 255, 0, 128]]
+        );
+        assert_eq!(palette.hex(), vec!["#000000", "#ff8000", "#00ff0080"]);
+        assert_eq!(palette.nearest([250, 100, 10, 255]), [255, 128, 0, 255]);
+
+        for text in ["", "// nothing", "#12345", "#gggggg"] {
+            assert!(matches!(
+                Palette::parse(text),
+                Err(Error::InvalidPalette(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir();
+        let text = dir.join(format!("wfc-palette-{}.txt", std::process::id()));
+        std::fs::write(&text, "#102030 #405060\n").unwrap();
+        let palette = Palette::load(text.to_str().unwrap()).unwrap();
+        assert_eq!(palette.hex(), vec!["#102030", "#405060"]);
+        std::fs::remove_file(&text).unwrap();
+
+        // The distinct pixels of an image, in order of appearance
+        let swatches = dir.join(format!("wfc-palette-{}.png", std::process::id()));
+        let img = image::RgbaImage::from_fn(3, 1, |x, _| match x {
+            1 => image::Rgba([9, 9, 9, 255]),
+            _ => image::Rgba([1, 2, 3, 255]),
+        });
+        img.save(&swatches).unwrap();
+        let palette = Palette::load(swatches.to_str().unwrap()).unwrap();
+        assert_eq!(palette.colours, vec![[1, 2, 3, 255], [9, 9, 9, 255]]);
+        std::fs::remove_file(&swatches).unwrap();
+    }
+}
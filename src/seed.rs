@@ -0,0 +1,33 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+pub type Seed = u64;
+
+//
+// Derive an independent seed for a sub-task (block, retry, candidate...)
+// so that the result doesn't depend on which thread ends up running it.
+//
+// Uses the SplitMix64 finalizer to scatter neighbouring streams.
+//
+pub fn derive_seed(seed: Seed, stream: u64) -> Seed {
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn make_rng(seed: Seed) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::derive_seed;
+
+    #[test]
+    fn test_derive_seed() {
+        assert_eq!(derive_seed(42, 3), derive_seed(42, 3));
+        assert_ne!(derive_seed(42, 3), derive_seed(42, 4));
+        assert_ne!(derive_seed(42, 3), derive_seed(43, 3));
+    }
+}
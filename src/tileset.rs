@@ -0,0 +1,225 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::image_reader::Image;
+
+//
+// Symmetry class of a tile, decides how many distinct
+// variants the tile has under rotation and reflection.
+//
+// Variant i < 4 is the base tile rotated counter-clockwise i times,
+// variant i >= 4 is variant i - 4 mirrored horizontally.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum TileSymmetry {
+    // Same under every rotation and reflection
+    #[default]
+    X,
+    // Straight line
+    I,
+    // Corner
+    L,
+    // T junction
+    T,
+    // Diagonal
+    #[serde(rename = "\\")]
+    Diagonal,
+    // No symmetry at all
+    F,
+}
+
+impl TileSymmetry {
+    pub fn cardinality(self) -> usize {
+        match self {
+            TileSymmetry::X => 1,
+            TileSymmetry::I | TileSymmetry::Diagonal => 2,
+            TileSymmetry::L | TileSymmetry::T => 4,
+            TileSymmetry::F => 8,
+        }
+    }
+
+    //
+    // Variant reached by rotating the given variant counter-clockwise
+    //
+    pub fn rotate(self, variant: usize) -> usize {
+        match self {
+            TileSymmetry::X => variant,
+            TileSymmetry::I | TileSymmetry::Diagonal => 1 - variant,
+            TileSymmetry::L | TileSymmetry::T => (variant + 1) % 4,
+            TileSymmetry::F if variant < 4 => (variant + 1) % 4,
+            TileSymmetry::F => 4 + (variant + 3) % 4,
+        }
+    }
+
+    //
+    // Variant reached by mirroring the given variant horizontally
+    //
+    pub fn reflect(self, variant: usize) -> usize {
+        match self {
+            TileSymmetry::X | TileSymmetry::I => variant,
+            TileSymmetry::Diagonal => 1 - variant,
+            TileSymmetry::L if variant.is_multiple_of(2) => variant + 1,
+            TileSymmetry::L => variant - 1,
+            TileSymmetry::T if variant.is_multiple_of(2) => variant,
+            TileSymmetry::T => 4 - variant,
+            TileSymmetry::F if variant < 4 => variant + 4,
+            TileSymmetry::F => variant - 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub name: String,
+    pub image: Image,
+    pub symmetry: TileSymmetry,
+    pub weight: f32,
+}
+
+//
+// `left` may appear directly to the left of `right`.
+// Both are written as "<name> <variant>", the variant defaults to 0.
+//
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NeighborRule {
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub tiles: Vec<Tile>,
+    pub neighbors: Vec<NeighborRule>,
+}
+
+// On-disk form of a tile, the image is a path
+// relative to the tileset description
+#[derive(Debug, Deserialize)]
+struct TileDescription {
+    name: String,
+    image: String,
+    #[serde(default)]
+    symmetry: TileSymmetry,
+    #[serde(default = "default_weight")]
+    weight: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TilesetDescription {
+    tiles: Vec<TileDescription>,
+    neighbors: Vec<NeighborRule>,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl Tileset {
+    //
+    // Load a JSON tileset description and every tile image it refers to
+    //
+    pub fn load(path: &str) -> Result<Tileset> {
+        let file = std::fs::File::open(path)?;
+        let description: TilesetDescription = serde_json::from_reader(file)
+            .map_err(|err| Error::Tileset(format!("{}: {}", path, err)))?;
+
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let tiles = description
+            .tiles
+            .into_iter()
+            .map(|tile| {
+                let img = image::open(dir.join(&tile.image))?;
+                Ok(Tile {
+                    name: tile.name,
+                    image: Image::from_dynamic(&img),
+                    symmetry: tile.symmetry,
+                    weight: tile.weight,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Tileset {
+            tiles,
+            neighbors: description.neighbors,
+        })
+    }
+
+    //
+    // Split "<name> <variant>" into the tile position and variant
+    //
+    pub fn find(&self, tile: &str) -> Result<(usize, usize)> {
+        let mut parts = tile.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        let variant = match parts.next() {
+            Some(variant) => variant
+                .parse::<usize>()
+                .map_err(|_| Error::Tileset(format!("invalid variant in \"{}\"", tile)))?,
+            None => 0,
+        };
+
+        let idx = self
+            .tiles
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or_else(|| Error::Tileset(format!("unknown tile \"{}\"", name)))?;
+
+        if variant >= self.tiles[idx].symmetry.cardinality() {
+            return Err(Error::Tileset(format!(
+                "tile \"{}\" has no variant {}",
+                name, variant
+            )));
+        }
+
+        Ok((idx, variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TileSymmetry, Tileset};
+
+    const ALL_SYMMETRIES: [TileSymmetry; 6] = [
+        TileSymmetry::X,
+        TileSymmetry::I,
+        TileSymmetry::L,
+        TileSymmetry::T,
+        TileSymmetry::Diagonal,
+        TileSymmetry::F,
+    ];
+
+    #[test]
+    fn test_symmetry_group() {
+        for symmetry in ALL_SYMMETRIES {
+            for variant in 0..symmetry.cardinality() {
+                let rotate = |v| symmetry.rotate(v);
+                let reflect = |v| symmetry.reflect(v);
+
+                // Four rotations or two reflections are the identity
+                assert_eq!(rotate(rotate(rotate(rotate(variant)))), variant);
+                assert_eq!(reflect(reflect(variant)), variant);
+
+                // Reflecting reverses the direction of rotation
+                assert_eq!(
+                    reflect(rotate(reflect(variant))),
+                    rotate(rotate(rotate(variant)))
+                );
+                assert!(rotate(variant) < symmetry.cardinality());
+                assert!(reflect(variant) < symmetry.cardinality());
+            }
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let tileset = Tileset::load("samples/Pipes/tileset.json").unwrap();
+
+        assert_eq!(tileset.tiles.len(), 5);
+        assert_eq!(tileset.find("corner 3").unwrap(), (3, 3));
+        assert_eq!(tileset.find("cross").unwrap(), (1, 0));
+        assert!(tileset.find("corner 4").is_err());
+        assert!(tileset.find("pipe 0").is_err());
+        assert!(tileset.tiles.iter().all(|t| t.image.width == 3));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::core::TileIndex;
+use crate::data::direction::Direction;
+use crate::data::grid2d::Grid2D;
+use crate::data::vector2::Vector2;
+use crate::error::{Error, Result};
+use crate::image_reader::Image;
+use crate::model::{Model, ModelKind};
+
+//
+// A place where the output doesn't satisfy the model
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    // The neighbour of `coord` in `direction` holds a tile
+    // which the model doesn't allow next to the one at `coord`
+    Adjacency {
+        coord: Vector2,
+        direction: Direction,
+        tile: TileIndex,
+        neighbour: TileIndex,
+    },
+
+    // The pixels at `coord` don't match any pattern of the model
+    UnknownPattern {
+        coord: Vector2,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Adjacency {
+                coord,
+                direction,
+                tile,
+                neighbour,
+            } => write!(
+                f,
+                "({}, {}): tile {} can't have tile {} on its {:?} side",
+                coord.x, coord.y, tile, neighbour, direction
+            ),
+            Violation::UnknownPattern { coord } => {
+                write!(f, "({}, {}): no pattern matches", coord.x, coord.y)
+            }
+        }
+    }
+}
+
+//
+// Check every pair of neighbouring tiles against the adjacency rules
+// of the model, pairs across the edges are checked for periodic outputs
+//
+pub fn validate(model: &Model, tiles: &Grid2D<TileIndex>, periodic: bool) -> Vec<Violation> {
+    let tiles = Grid2D {
+        width: tiles.width,
+        height: tiles.height,
+        data: tiles.data.iter().copied().map(Some).collect(),
+    };
+    check_adjacency(model, &tiles, periodic)
+}
+
+//
+// Validate a rendered output, the tiles are read back from the pixels.
+//
+// For the overlapping model every NxN window has to be a pattern,
+// windows which stick out of a bounded output are covered by the
+// ones next to them and aren't checked on their own.
+//
+pub fn validate_image(model: &Model, image: &Image, periodic: bool) -> Result<Vec<Violation>> {
+    let merged;
+    let image = if model.merges_transparent() {
+        merged = image.merge_transparent();
+        &merged
+    } else {
+        image
+    };
+
+    let tiles = read_tiles(model, image, periodic)?;
+
+    let mut violations: Vec<Violation> = tiles
+        .enumerate()
+        .filter(|(_, tile)| tile.is_none())
+        .map(|(coord, _)| Violation::UnknownPattern { coord })
+        .collect();
+    violations.extend(check_adjacency(model, &tiles, periodic));
+
+    Ok(violations)
+}
+
+//
+// Find the sample behind every cell of the image, None if there is none
+//
+fn read_tiles(model: &Model, image: &Image, periodic: bool) -> Result<Grid2D<Option<TileIndex>>> {
+    let (width, height, step) = match model.kind {
+        ModelKind::Overlapping if periodic => (image.width, image.height, 1),
+        ModelKind::Overlapping => (
+            (image.width + 1).saturating_sub(model.pattern_width),
+            (image.height + 1).saturating_sub(model.pattern_height),
+            1,
+        ),
+        ModelKind::Tiled => {
+            let tile_size = model.cell_size();
+            if !image.width.is_multiple_of(tile_size) || !image.height.is_multiple_of(tile_size) {
+                return Err(Error::Tileset(format!(
+                    "output is not made of whole {}x{} tiles",
+                    tile_size, tile_size
+                )));
+            }
+            (image.width / tile_size, image.height / tile_size, tile_size)
+        }
+    };
+
+    let sample_ids: HashMap<_, _> = model
+        .samples
+        .iter()
+        .enumerate()
+        .map(|(id, sample)| (sample, id))
+        .collect();
+
+    let region = &model.samples[0].region;
+    let (region_width, region_height) = (region.width as u32, region.height as u32);
+    let data = (0..width * height)
+        .map(|idx| {
+            let x = ((idx % width) * step) as u32;
+            let y = ((idx / width) * step) as u32;
+            let region = image.get_region(&x, &y, &region_width, &region_height);
+            sample_ids.get(&region).copied()
+        })
+        .collect();
+
+    Ok(Grid2D {
+        width,
+        height,
+        data,
+    })
+}
+
+fn check_adjacency(
+    model: &Model,
+    tiles: &Grid2D<Option<TileIndex>>,
+    periodic: bool,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    for (coord, tile) in tiles.enumerate() {
+        for direction in [Direction::Right, Direction::Down] {
+            let mut other = coord.neighbor(direction);
+            if periodic {
+                other = tiles.wrap(other);
+            }
+
+            // Unknown tiles are reported on their own
+            let (tile, neighbour) = match (tile, tiles.get(other)) {
+                (Some(tile), Some(Some(neighbour))) => (*tile, *neighbour),
+                _ => continue,
+            };
+
+            if !model.adjacency_rule[tile][direction.to_idx()].contains(neighbour) {
+                violations.push(Violation::Adjacency {
+                    coord,
+                    direction,
+                    tile,
+                    neighbour,
+                });
+            }
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, validate_image, Violation};
+    use crate::data::direction::Direction;
+    use crate::data::grid2d::Grid2D;
+    use crate::data::vector2::Vector2;
+    use crate::image_reader::Image;
+    use crate::model::{Model, ModelOptions};
+
+    #[test]
+    fn test_validate_tiles() {
+        let model = Model::create("samples/ProcessExample.png", &ModelOptions::new(3)).unwrap();
+
+        // Pick a tile which isn't allowed to its own right
+        let tile = (0..model.size())
+            .find(|&t| !model.adjacency_rule[t][Direction::Right.to_idx()].contains(t))
+            .unwrap();
+        let tiles = Grid2D::init(2, 1, tile);
+
+        assert_eq!(
+            validate(&model, &tiles, false),
+            vec![Violation::Adjacency {
+                coord: Vector2 { x: 0, y: 0 },
+                direction: Direction::Right,
+                tile,
+                neighbour: tile,
+            }]
+        );
+
+        // Wrapping around adds the pair over the seam as well
+        let across = validate(&model, &tiles, true)
+            .into_iter()
+            .filter(|v| matches!(v, Violation::Adjacency { direction, .. } if *direction == Direction::Right))
+            .count();
+        assert_eq!(across, 2);
+    }
+
+    #[test]
+    fn test_validate_image() {
+        let img = image::open("samples/ProcessExample.png").unwrap();
+        let input = Image::from_dynamic(&img);
+        let model = Model::from_image(&input, &ModelOptions::new(3)).unwrap();
+
+        // The input is made of nothing but its own patterns
+        assert!(validate_image(&model, &input, true).unwrap().is_empty());
+        assert!(validate_image(&model, &input, false).unwrap().is_empty());
+
+        // A pixel of a colour the input doesn't have breaks every window over it
+        let mut broken = input.clone();
+        broken.set_colour(Vector2 { x: 0, y: 0 }, [1, 2, 3, 255]);
+        let violations = validate_image(&model, &broken, false).unwrap();
+        assert_eq!(
+            violations,
+            vec![Violation::UnknownPattern {
+                coord: Vector2 { x: 0, y: 0 }
+            }]
+        );
+    }
+}